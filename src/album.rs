@@ -1,12 +1,19 @@
-use crate::apple_music::AppleMusic;
+use crate::apple_music::AppleMusicProvider;
+use crate::bandcamp::BandcampProvider;
+use crate::cache::MatchCache;
+use crate::config::Config;
+use crate::deezer::DeezerProvider;
 use crate::error::Result;
-use crate::service::Source;
-use crate::spotify::Spotify;
+use crate::overrides::{with_overrides_and_cache, Overrides};
+use crate::service::{MusicService, Source};
+use crate::spotify::SpotifyProvider;
 use crate::track::Track;
+use crate::youtube::YouTubeProvider;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct Album {
@@ -17,12 +24,17 @@ pub struct Album {
     pub release_year: usize,
     pub release_month: Option<usize>,
     pub release_day: Option<usize>,
+    #[serde(default)]
+    pub artists: Vec<String>,
+    pub upc: Option<String>,
+    pub label: Option<String>,
 }
 
 impl Album {
     pub async fn download_tracks(
         &self,
         client: &Client,
+        config: &Config,
         download_path: &Path,
         add_metadata: bool,
     ) -> Result<()> {
@@ -33,31 +45,24 @@ impl Album {
             download_path.to_string_lossy()
         );
 
-        std::fs::create_dir_all(&download_path)?;
+        std::fs::create_dir_all(download_path)?;
 
-        let mut count = 1;
-        let mut track_names: Vec<String> = Vec::with_capacity(self.tracks.len());
-        let mut download_futures = Vec::with_capacity(self.tracks.len());
-
-        for track in &self.tracks {
-            track_names.push(format!(
-                "({}) {} - {}",
-                count,
-                track.name,
-                track.artists.join(", ")
-            ));
-            count += 1;
-        }
+        let track_names: Vec<String> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(i, track)| format!("({}) {} - {}", i + 1, track.name, track.artists.join(", ")))
+            .collect();
 
-        count = 0;
-        for track in &self.tracks {
+        let mut download_futures = Vec::with_capacity(self.tracks.len());
+        for (track, track_name) in self.tracks.iter().zip(&track_names) {
             download_futures.push(track.download(
                 client,
+                config,
                 download_path,
-                &track_names[count],
+                track_name,
                 add_metadata,
             ));
-            count += 1;
         }
 
         futures::future::join_all(download_futures).await;
@@ -79,51 +84,99 @@ impl Album {
 
         Ok(())
     }
-    pub async fn add_spotify(&mut self, client: &Client, auth: &str) -> Result<()> {
-        let mut spotify_service_futures = Vec::with_capacity(self.tracks.len());
-        for track in &mut self.tracks {
-            spotify_service_futures.push(track.add_spotify(client, auth));
-        }
-        let results = futures::future::join_all(spotify_service_futures).await;
-        let mut count = 1;
-        for result in results {
-            match result {
-                Ok(..) => (),
-                Err(e) => {
-                    log::warn!("\tSkipping adding Spotify to track {}: {}", count + 1, e)
-                }
-            };
-            count += 1;
-        }
 
-        Ok(())
+    pub async fn add_service(&mut self, client: &Client, service: &dyn MusicService) -> Result<()> {
+        service
+            .create_services_for_tracks(client, &mut self.tracks)
+            .await
     }
+
+    /// Add `service`, applying `overrides` and then answering from `cache` for
+    /// each track before searching
+    async fn add_wrapped_service(
+        &mut self,
+        client: &Client,
+        service: Box<dyn MusicService>,
+        overrides: Arc<Overrides>,
+        cache: Option<Arc<MatchCache>>,
+    ) -> Result<()> {
+        self.add_service(client, &with_overrides_and_cache(service, overrides, cache))
+            .await
+    }
+
+    pub async fn add_spotify(&mut self, client: &Client, auth: &str) -> Result<()> {
+        self.add_wrapped_service(
+            client,
+            Box::new(SpotifyProvider::new(auth)),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
+    }
+
     pub async fn add_apple_music(&mut self, client: &Client, auth: &str) -> Result<()> {
-        todo!()
+        self.add_wrapped_service(
+            client,
+            Box::new(AppleMusicProvider::new(auth)),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
     }
 
     pub async fn add_youtube(&mut self, client: &Client) -> Result<()> {
-        todo!()
+        self.add_wrapped_service(
+            client,
+            Box::new(YouTubeProvider::new()),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
     }
 
     pub async fn add_bandcamp(&mut self, client: &Client) -> Result<()> {
-        todo!()
+        self.add_wrapped_service(
+            client,
+            Box::new(BandcampProvider::new()),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
+    }
+
+    pub async fn add_deezer(&mut self, client: &Client) -> Result<()> {
+        self.add_wrapped_service(
+            client,
+            Box::new(DeezerProvider::new()),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
     }
 
     pub fn from_file(file_path: &Path) -> Result<Self> {
         Ok(serde_json::from_str(&std::fs::read_to_string(file_path)?)?)
     }
 
+    pub async fn from_service_id(
+        client: &Client,
+        service: &dyn MusicService,
+        album_id: &str,
+    ) -> Result<Self> {
+        service.create_album_from_id(client, album_id).await
+    }
+
     pub async fn from_spotify_id(
         client: &Client,
         spotify_auth: &str,
         spotify_album_id: &str,
     ) -> Result<Self> {
-        todo!();
-        //let album: Self =
-        //    Spotify::create_album_from_id(client, spotify_auth, spotify_album_id).await?;
-
-        //Ok(album)
+        Self::from_service_id(
+            client,
+            &SpotifyProvider::new(spotify_auth),
+            spotify_album_id,
+        )
+        .await
     }
 
     pub async fn from_apple_music_id(
@@ -131,11 +184,23 @@ impl Album {
         apple_music_auth: &str,
         apple_music_album_id: &str,
     ) -> Result<Self> {
-        todo!();
-        //let album: Album =
-        //    AppleMusic::create_album_from_id(client, apple_music_auth, apple_music_album_id)
-        //        .await?;
+        Self::from_service_id(
+            client,
+            &AppleMusicProvider::new(apple_music_auth),
+            apple_music_album_id,
+        )
+        .await
+    }
+
+    pub async fn from_youtube_id(client: &Client, youtube_album_id: &str) -> Result<Self> {
+        Self::from_service_id(client, &YouTubeProvider::new(), youtube_album_id).await
+    }
+
+    pub async fn from_deezer_id(client: &Client, deezer_album_id: &str) -> Result<Self> {
+        Self::from_service_id(client, &DeezerProvider::new(), deezer_album_id).await
+    }
 
-        //Ok(album)
+    pub async fn from_bandcamp_url(client: &Client, bandcamp_album_url: &str) -> Result<Self> {
+        Self::from_service_id(client, &BandcampProvider::new(), bandcamp_album_url).await
     }
 }
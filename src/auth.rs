@@ -0,0 +1,829 @@
+//! Bearer tokens for the services that need them.
+//!
+//! Providers hold an `Arc<dyn CredentialProvider>` and ask it for a token before
+//! talking to the API, so tokens are fetched lazily, shared between everything
+//! built from the same provider and replaced shortly before they expire.
+
+use crate::apple_music::AppleMusic;
+use crate::config::ServiceConfig;
+use crate::error::{Error, Result};
+use crate::spotify::Spotify;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::pkcs8::DecodePrivateKey;
+use reqwest::{Client, Method, RequestBuilder, Response, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+/// Tokens are replaced this long before they expire
+const EXPIRY_MARGIN_MS: u64 = 60_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Token {
+    pub access_token: String,
+    /// Milliseconds since the Unix epoch, `None` if it doesn't expire
+    pub expires_at_ms: Option<u64>,
+    /// Used to get a new `access_token` without logging in again
+    pub refresh_token: Option<String>,
+}
+
+/// Source of the bearer token sent to a service
+#[async_trait]
+pub trait CredentialProvider: Send + Sync {
+    /// A token that is valid now, fetching a new one if needed
+    async fn token(&self, client: &Client) -> Result<String>;
+
+    /// Replace `rejected`, a token the service answered 401 to. Concurrent
+    /// callers rejecting the same token share one new token.
+    async fn refresh(&self, _client: &Client, _rejected: &str) -> Result<String> {
+        Err(Error::Unauthorized(
+            "token was rejected and cannot be refreshed".to_string(),
+        ))
+    }
+}
+
+/// Run `send` with a token from `credentials`. If the service rejects it, the
+/// token is refreshed and `send` is retried once.
+pub async fn authorized<T, F, Fut>(
+    client: &Client,
+    credentials: &dyn CredentialProvider,
+    send: F,
+) -> Result<T>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let token: String = credentials.token(client).await?;
+    match send(token.to_owned()).await {
+        Err(Error::Unauthorized(e)) => {
+            log::info!("{}, refreshing token", e);
+            send(credentials.refresh(client, &token).await?).await
+        }
+        result => result,
+    }
+}
+
+/// A token passed in as-is, e.g. with `--ST`. It is never refreshed.
+pub struct StaticToken(pub String);
+
+/// Spotify app token from the Client Credentials flow. It can read the catalog
+/// and public playlists, but not anything belonging to a user.
+pub struct SpotifyClientCredentials {
+    client_id: String,
+    client_secret: String,
+    config: ServiceConfig,
+    store: TokenStore,
+}
+
+/// Spotify user token from the Authorization Code flow with PKCE.
+///
+/// `login` has to be run once, after which the token is refreshed with its
+/// refresh token. Use `with_cache_file` to keep the login between runs.
+pub struct SpotifyPkce {
+    client_id: String,
+    redirect_uri: String,
+    config: ServiceConfig,
+    store: TokenStore,
+}
+
+/// What to send the user to, and what to keep until they come back
+#[derive(Debug, Clone, PartialEq)]
+pub struct PkceAuthorization {
+    pub url: String,
+    pub code_verifier: String,
+    pub state: String,
+}
+
+/// Anonymous token of the Spotify web player, see `Spotify::get_public_session_info`
+pub struct SpotifyWebPlayer {
+    config: ServiceConfig,
+    store: TokenStore,
+}
+
+/// Apple Music developer token signed with a MusicKit private key (`.p8` file)
+pub struct AppleDeveloperToken {
+    team_id: String,
+    key_id: String,
+    signing_key: SigningKey,
+    lifetime: Duration,
+    store: TokenStore,
+}
+
+/// Anonymous token of the Apple Music web player, see `AppleMusic::get_web_player_token`
+pub struct AppleMusicWebPlayer {
+    config: ServiceConfig,
+    store: TokenStore,
+}
+
+/// Last token of a provider, kept in memory and optionally in a JSON file
+struct TokenStore {
+    path: Option<PathBuf>,
+    token: Mutex<Option<Token>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawToken {
+    access_token: String,
+    expires_in: u64,
+    refresh_token: Option<String>,
+}
+
+impl Token {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at_ms
+            .is_some_and(|expires_at_ms| now_ms() + EXPIRY_MARGIN_MS >= expires_at_ms)
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for StaticToken {
+    async fn token(&self, _client: &Client) -> Result<String> {
+        Ok(self.0.to_owned())
+    }
+}
+
+impl SpotifyClientCredentials {
+    pub fn new(client_id: &str, client_secret: &str) -> Self {
+        Self::with_config(client_id, client_secret, ServiceConfig::spotify())
+    }
+
+    pub fn with_config(client_id: &str, client_secret: &str, config: ServiceConfig) -> Self {
+        SpotifyClientCredentials {
+            client_id: client_id.to_owned(),
+            client_secret: client_secret.to_owned(),
+            config,
+            store: TokenStore::new(None),
+        }
+    }
+
+    /// Keep the token in `path` so it is reused by later runs
+    pub fn with_cache_file(mut self, path: &Path) -> Self {
+        self.store = TokenStore::new(Some(path));
+        self
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for SpotifyClientCredentials {
+    async fn token(&self, client: &Client) -> Result<String> {
+        self.store
+            .get_or_fetch(|_| {
+                request_spotify_token(
+                    client,
+                    &self.config,
+                    &[("grant_type", "client_credentials")],
+                    Some((&self.client_id, &self.client_secret)),
+                )
+            })
+            .await
+    }
+
+    async fn refresh(&self, client: &Client, rejected: &str) -> Result<String> {
+        self.store.invalidate(rejected).await;
+        self.token(client).await
+    }
+}
+
+impl SpotifyPkce {
+    pub const DEFAULT_REDIRECT_URI: &'static str = "http://127.0.0.1:8888/callback";
+    /// Enough to read the user's playlists and saved albums
+    pub const DEFAULT_SCOPES: [&'static str; 3] = [
+        "playlist-read-private",
+        "playlist-read-collaborative",
+        "user-library-read",
+    ];
+
+    pub fn new(client_id: &str, redirect_uri: &str) -> Self {
+        Self::with_config(client_id, redirect_uri, ServiceConfig::spotify())
+    }
+
+    pub fn with_config(client_id: &str, redirect_uri: &str, config: ServiceConfig) -> Self {
+        SpotifyPkce {
+            client_id: client_id.to_owned(),
+            redirect_uri: redirect_uri.to_owned(),
+            config,
+            store: TokenStore::new(None),
+        }
+    }
+
+    /// Keep the token in `path` so later runs don't need to log in again
+    pub fn with_cache_file(mut self, path: &Path) -> Self {
+        self.store = TokenStore::new(Some(path));
+        self
+    }
+
+    /// Start a login: the user opens `url` and is sent back to `redirect_uri`
+    /// with a code to pass to `exchange_code`
+    pub fn authorization(&self, scopes: &[&str]) -> Result<PkceAuthorization> {
+        let code_verifier: String = random_string(32)?;
+        let state: String = random_string(16)?;
+
+        let url: Url = Url::parse_with_params(
+            &format!("{}/authorize", self.config.auth_base_url),
+            &[
+                ("client_id", self.client_id.as_str()),
+                ("response_type", "code"),
+                ("redirect_uri", &self.redirect_uri),
+                ("code_challenge_method", "S256"),
+                ("code_challenge", &code_challenge(&code_verifier)),
+                ("state", &state),
+                ("scope", &scopes.join(" ")),
+            ],
+        )
+        .map_err(|e| Error::DatabaseError(format!("invalid Spotify auth URL: {}", e)))?;
+
+        Ok(PkceAuthorization {
+            url: url.to_string(),
+            code_verifier,
+            state,
+        })
+    }
+
+    /// Finish a login started with `authorization`
+    pub async fn exchange_code(
+        &self,
+        client: &Client,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<()> {
+        let token: Token = request_spotify_token(
+            client,
+            &self.config,
+            &[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("client_id", &self.client_id),
+                ("code_verifier", code_verifier),
+            ],
+            None,
+        )
+        .await?;
+
+        self.store.set(token).await;
+        Ok(())
+    }
+
+    /// Log in interactively: pass the authorization URL to `show_url` and wait for
+    /// the browser to be redirected to `redirect_uri`, which must point at this machine
+    pub async fn login(
+        &self,
+        client: &Client,
+        scopes: &[&str],
+        show_url: impl FnOnce(&str),
+    ) -> Result<()> {
+        let authorization: PkceAuthorization = self.authorization(scopes)?;
+        let redirect_uri: Url = Url::parse(&self.redirect_uri)
+            .map_err(|e| Error::InvalidInput(format!("invalid redirect URI: {}", e)))?;
+        let address: String = format!(
+            "{}:{}",
+            redirect_uri.host_str().unwrap_or("127.0.0.1"),
+            redirect_uri.port_or_known_default().unwrap_or(80)
+        );
+        let listener: TcpListener = TcpListener::bind(&address).await?;
+
+        show_url(&authorization.url);
+
+        let code: String = loop {
+            let (mut stream, _) = listener.accept().await?;
+            if let Some(code) =
+                read_redirect(&mut stream, redirect_uri.path(), &authorization.state).await?
+            {
+                break code;
+            }
+        };
+
+        self.exchange_code(client, &code, &authorization.code_verifier)
+            .await
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for SpotifyPkce {
+    async fn token(&self, client: &Client) -> Result<String> {
+        self.store
+            .get_or_fetch(|previous| async move {
+                let refresh_token: String = match previous.and_then(|token| token.refresh_token) {
+                    Some(refresh_token) => refresh_token,
+                    None => {
+                        return Err(Error::Unauthorized(
+                            "Not logged in to Spotify, run `songvert login spotify`".to_string(),
+                        ))
+                    }
+                };
+
+                let token: Token = request_spotify_token(
+                    client,
+                    &self.config,
+                    &[
+                        ("grant_type", "refresh_token"),
+                        ("refresh_token", &refresh_token),
+                        ("client_id", &self.client_id),
+                    ],
+                    None,
+                )
+                .await?;
+
+                // Spotify doesn't always hand out a new refresh token
+                Ok(Token {
+                    refresh_token: token.refresh_token.or(Some(refresh_token)),
+                    ..token
+                })
+            })
+            .await
+    }
+
+    async fn refresh(&self, client: &Client, rejected: &str) -> Result<String> {
+        self.store.invalidate(rejected).await;
+        self.token(client).await
+    }
+}
+
+impl SpotifyWebPlayer {
+    pub fn new() -> Self {
+        Self::with_config(ServiceConfig::spotify())
+    }
+
+    pub fn with_config(config: ServiceConfig) -> Self {
+        SpotifyWebPlayer {
+            config,
+            store: TokenStore::new(None),
+        }
+    }
+}
+
+impl Default for SpotifyWebPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for SpotifyWebPlayer {
+    async fn token(&self, client: &Client) -> Result<String> {
+        self.store
+            .get_or_fetch(|_| async move {
+                let session_info = Spotify::get_public_session_info(client, &self.config).await?;
+                Ok(Token {
+                    access_token: session_info.access_token,
+                    expires_at_ms: Some(session_info.access_token_expiration_timestamp_ms as u64),
+                    refresh_token: None,
+                })
+            })
+            .await
+    }
+
+    async fn refresh(&self, client: &Client, rejected: &str) -> Result<String> {
+        self.store.invalidate(rejected).await;
+        self.token(client).await
+    }
+}
+
+impl AppleDeveloperToken {
+    /// Twelve hours, well below the six-month maximum Apple accepts, since a new
+    /// token is signed whenever the old one expires
+    pub const DEFAULT_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+
+    /// `private_key` is the PEM contents of the `.p8` file downloaded from Apple
+    pub fn new(team_id: &str, key_id: &str, private_key: &str) -> Result<Self> {
+        let signing_key: SigningKey = SigningKey::from_pkcs8_pem(private_key)
+            .map_err(|e| Error::InvalidInput(format!("invalid MusicKit private key: {}", e)))?;
+
+        Ok(AppleDeveloperToken {
+            team_id: team_id.to_owned(),
+            key_id: key_id.to_owned(),
+            signing_key,
+            lifetime: Self::DEFAULT_LIFETIME,
+            store: TokenStore::new(None),
+        })
+    }
+
+    pub fn from_file(team_id: &str, key_id: &str, private_key_path: &Path) -> Result<Self> {
+        Self::new(team_id, key_id, &std::fs::read_to_string(private_key_path)?)
+    }
+
+    pub fn with_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Mint a new ES256 token valid for `lifetime`
+    pub fn sign(&self) -> Result<Token> {
+        let issued_at: u64 = now_ms() / 1000;
+        let expires_at: u64 = issued_at + self.lifetime.as_secs();
+
+        let header = serde_json::json!({ "alg": "ES256", "kid": self.key_id });
+        let claims =
+            serde_json::json!({ "iss": self.team_id, "iat": issued_at, "exp": expires_at });
+        let message: String = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?)
+        );
+        let signature: Signature = self.signing_key.sign(message.as_bytes());
+
+        Ok(Token {
+            access_token: format!(
+                "{}.{}",
+                message,
+                URL_SAFE_NO_PAD.encode(signature.to_bytes())
+            ),
+            expires_at_ms: Some(expires_at * 1000),
+            refresh_token: None,
+        })
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for AppleDeveloperToken {
+    async fn token(&self, _client: &Client) -> Result<String> {
+        self.store.get_or_fetch(|_| async { self.sign() }).await
+    }
+
+    async fn refresh(&self, client: &Client, rejected: &str) -> Result<String> {
+        self.store.invalidate(rejected).await;
+        self.token(client).await
+    }
+}
+
+impl AppleMusicWebPlayer {
+    pub fn new() -> Self {
+        Self::with_config(ServiceConfig::apple_music())
+    }
+
+    pub fn with_config(config: ServiceConfig) -> Self {
+        AppleMusicWebPlayer {
+            config,
+            store: TokenStore::new(None),
+        }
+    }
+
+    /// Keep the token in `path` so later runs don't need to scrape it again
+    pub fn with_cache_file(mut self, path: &Path) -> Self {
+        self.store = TokenStore::new(Some(path));
+        self
+    }
+}
+
+impl Default for AppleMusicWebPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for AppleMusicWebPlayer {
+    async fn token(&self, client: &Client) -> Result<String> {
+        self.store
+            .get_or_fetch(|_| async move {
+                let access_token: String =
+                    AppleMusic::get_web_player_token(client, &self.config).await?;
+                Ok(Token {
+                    expires_at_ms: jwt_expiry_ms(&access_token),
+                    access_token,
+                    refresh_token: None,
+                })
+            })
+            .await
+    }
+
+    async fn refresh(&self, client: &Client, rejected: &str) -> Result<String> {
+        self.store.invalidate(rejected).await;
+        self.token(client).await
+    }
+}
+
+impl TokenStore {
+    fn new(path: Option<&Path>) -> Self {
+        let token: Option<Token> = path
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|raw| serde_json::from_str(&raw).ok());
+
+        TokenStore {
+            path: path.map(Path::to_owned),
+            token: Mutex::new(token),
+        }
+    }
+
+    /// The stored token if it is still valid, otherwise the one returned by
+    /// `fetch`, which is given the expired token (e.g. for its refresh token).
+    /// The lock is held while fetching so concurrent callers share one request.
+    async fn get_or_fetch<F, Fut>(&self, fetch: F) -> Result<String>
+    where
+        F: FnOnce(Option<Token>) -> Fut,
+        Fut: Future<Output = Result<Token>>,
+    {
+        let mut stored = self.token.lock().await;
+        if let Some(token) = stored.as_ref().filter(|token| !token.is_expired()) {
+            return Ok(token.access_token.to_owned());
+        }
+
+        let token: Token = fetch(stored.take()).await?;
+        self.save(&token);
+        let access_token: String = token.access_token.to_owned();
+        *stored = Some(token);
+        Ok(access_token)
+    }
+
+    /// Treat `rejected` as expired, if it is still the stored token
+    async fn invalidate(&self, rejected: &str) {
+        if let Some(token) = self.token.lock().await.as_mut() {
+            if token.access_token == rejected {
+                token.expires_at_ms = Some(0);
+            }
+        }
+    }
+
+    async fn set(&self, token: Token) {
+        self.save(&token);
+        *self.token.lock().await = Some(token);
+    }
+
+    fn save(&self, token: &Token) {
+        let path: &Path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let result: Result<()> = (|| {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            write_private_file(path, serde_json::to_string(token)?.as_bytes())?;
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            log::warn!(
+                "\tUnable to save token to {}: {}",
+                path.to_string_lossy(),
+                e
+            );
+        }
+    }
+}
+
+/// Write `contents` to `path`, readable and writable only by the current user
+/// since it can hold a refresh token
+fn write_private_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options: std::fs::OpenOptions = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file: std::fs::File = options.open(path)?;
+    // `mode` only applies to new files, so also fix up ones written before
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    std::io::Write::write_all(&mut file, contents)
+}
+
+/// POST `form` to the Spotify accounts token endpoint
+async fn request_spotify_token(
+    client: &Client,
+    config: &ServiceConfig,
+    form: &[(&str, &str)],
+    client_credentials: Option<(&str, &str)>,
+) -> Result<Token> {
+    let mut request: RequestBuilder = config
+        .request_url(
+            client,
+            Method::POST,
+            &format!("{}/api/token", config.auth_base_url),
+        )
+        .form(form);
+    if let Some((client_id, client_secret)) = client_credentials {
+        request = request.basic_auth(client_id, Some(client_secret));
+    }
+    let response: Response = config.send(request).await?;
+
+    let raw_token: RawToken = serde_json::from_str(&response.text().await?)?;
+    Ok(Token {
+        access_token: raw_token.access_token,
+        expires_at_ms: Some(now_ms() + raw_token.expires_in * 1000),
+        refresh_token: raw_token.refresh_token,
+    })
+}
+
+/// Answer one request to the redirect URI, returning the code if it was the
+/// login redirect. Other requests (e.g. for a favicon) get a 404 and `None`.
+async fn read_redirect(stream: &mut TcpStream, path: &str, state: &str) -> Result<Option<String>> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; 4096];
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n: usize = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+
+    let head: String = String::from_utf8_lossy(&buffer).into_owned();
+    let target: &str = head.split(' ').nth(1).unwrap_or("/");
+    let url: Url = Url::parse(&format!("http://localhost{}", target))
+        .map_err(|e| Error::DatabaseError(format!("invalid redirect: {}", e)))?;
+
+    if url.path() != path {
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(None);
+    }
+
+    let query_value = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    let result: Result<String> = if query_value("state").as_deref() != Some(state) {
+        Err(Error::Unauthorized(
+            "Spotify login state does not match".to_string(),
+        ))
+    } else if let Some(code) = query_value("code") {
+        Ok(code)
+    } else {
+        Err(Error::Unauthorized(format!(
+            "Spotify login failed: {}",
+            query_value("error").unwrap_or_default()
+        )))
+    };
+
+    let message: &str = match result {
+        Ok(..) => "Logged in to Spotify, you can close this window.",
+        Err(..) => "Unable to log in to Spotify, see songvert for details.",
+    };
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                message.len(),
+                message
+            )
+            .as_bytes(),
+        )
+        .await?;
+
+    result.map(Some)
+}
+
+/// `exp` claim of a JWT in milliseconds, without checking its signature
+fn jwt_expiry_ms(jwt: &str) -> Option<u64> {
+    let claims: Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(jwt.split('.').nth(1)?).ok()?).ok()?;
+    Some(claims.get("exp")?.as_u64()? * 1000)
+}
+
+/// Base64url SHA-256 of `code_verifier`, as required by the S256 method
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// `len` random bytes as a base64url string
+fn random_string(len: usize) -> Result<String> {
+    let mut bytes: Vec<u8> = vec![0; len];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| Error::DatabaseError(format!("unable to generate random bytes: {}", e)))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        auth::{
+            code_challenge, jwt_expiry_ms, AppleDeveloperToken, AppleMusicWebPlayer,
+            CredentialProvider, SpotifyClientCredentials, SpotifyPkce, Token, TokenStore,
+        },
+        config::ServiceConfig,
+        replay::ReplayServer,
+    };
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use p256::ecdsa::{signature::Verifier, Signature};
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    #[test]
+    fn pkce_authorization_url() {
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mJ92K1ZvvXDkdBkyD5oC3-Rq6Lmcdo"),
+            "LOiGrwahsM8WmKQsRisgZ9FYAaFPUbCDIB0Kr7lGs9M"
+        );
+
+        let authorization = SpotifyPkce::new("abc123", SpotifyPkce::DEFAULT_REDIRECT_URI)
+            .authorization(&SpotifyPkce::DEFAULT_SCOPES)
+            .unwrap();
+
+        assert!(authorization
+            .url
+            .starts_with("https://accounts.spotify.com/authorize?client_id=abc123"));
+        assert!(authorization.url.contains(&format!(
+            "code_challenge={}",
+            code_challenge(&authorization.code_verifier)
+        )));
+        assert!(authorization
+            .url
+            .contains(&format!("state={}", authorization.state)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn token_cache_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "songvert-token_cache_is_private-{}.json",
+            std::process::id()
+        ));
+        TokenStore::new(Some(&path))
+            .set(Token {
+                access_token: "BQDfirst".to_owned(),
+                expires_at_ms: None,
+                refresh_token: Some("AQDrefresh".to_owned()),
+            })
+            .await;
+
+        let mode: u32 = std::fs::metadata(&path).unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn client_credentials_token_is_reused() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("auth/spotify_client_credentials", ServiceConfig::spotify()).await;
+        let credentials =
+            SpotifyClientCredentials::with_config("replay", "replay", server.config());
+
+        // the cassette hands out a different token for the second request
+        assert_eq!(credentials.token(&client).await.unwrap(), "BQDfirst");
+        assert_eq!(credentials.token(&client).await.unwrap(), "BQDfirst");
+    }
+
+    #[test]
+    fn apple_developer_token_is_signed() {
+        let key_path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "auth"]
+            .iter()
+            .collect::<PathBuf>()
+            .join("AuthKey_TEST.p8");
+        let developer_token =
+            AppleDeveloperToken::from_file("TEAM123456", "KEY1234567", &key_path).unwrap();
+
+        let token: Token = developer_token.sign().unwrap();
+        let parts: Vec<&str> = token.access_token.split('.').collect();
+        let header: Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[0]).unwrap()).unwrap();
+        let claims: Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+
+        assert_eq!(header["alg"], "ES256");
+        assert_eq!(header["kid"], "KEY1234567");
+        assert_eq!(claims["iss"], "TEAM123456");
+        assert_eq!(
+            claims["exp"].as_u64().unwrap() - claims["iat"].as_u64().unwrap(),
+            AppleDeveloperToken::DEFAULT_LIFETIME.as_secs()
+        );
+        assert_eq!(token.expires_at_ms, jwt_expiry_ms(&token.access_token));
+
+        let signature: Signature =
+            Signature::from_slice(&URL_SAFE_NO_PAD.decode(parts[2]).unwrap()).unwrap();
+        developer_token
+            .signing_key
+            .verifying_key()
+            .verify(format!("{}.{}", parts[0], parts[1]).as_bytes(), &signature)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn apple_music_web_player_token() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("auth/apple_music_web_player", ServiceConfig::apple_music()).await;
+
+        let token: String = AppleMusicWebPlayer::with_config(server.config())
+            .token(&client)
+            .await
+            .unwrap();
+
+        assert!(token.starts_with("eyJhbGciOiJFUzI1NiIs"));
+        assert_eq!(jwt_expiry_ms(&token), Some(1_900_000_000_000));
+    }
+}
@@ -1,9 +1,16 @@
+use crate::album;
+use crate::config::ServiceConfig;
 use crate::error::{Error, Result};
-use crate::service::{Album, Artist};
+use crate::matcher::{Candidate, MatchReport, Matcher};
+use crate::normalize::{search_artists, search_title};
+use crate::service::{Album, Artist, MatchMethod, MatchProvenance, MusicService, Services, Source};
 use crate::track::Track;
-use reqwest::{Client, RequestBuilder, Response};
+use crate::utils::{date_from_timestamp, parse_response};
+use async_trait::async_trait;
+use reqwest::{Client, Method, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
@@ -17,6 +24,13 @@ pub struct Bandcamp {
     pub image: String,
     pub duration_ms: usize,
     pub streaming_url: Option<String>,
+    /// How this record was matched to its track
+    pub provenance: Option<MatchProvenance>,
+}
+
+/// `MusicService` implementation for Bandcamp
+pub struct BandcampProvider {
+    pub config: ServiceConfig,
 }
 
 // pub struct RawTrack {
@@ -60,6 +74,9 @@ struct RawAlbum {
     label: Option<String>,
     label_id: Option<usize>,
     num_downloadable_tracks: usize,
+    /// Page URL of each track, by track id, when read from an album page
+    #[serde(skip)]
+    track_urls: HashMap<usize, String>,
     // pub featured_track_id: usize,
     // pub about: String,
     // pub is_purchasable: bool,
@@ -111,6 +128,13 @@ struct RawAlbumTrack {
     // pub is_purchasable: bool,
 }
 
+/// Entry of `trackinfo` in the `data-tralbum` attribute of a track or album page
+#[derive(Deserialize, Debug, PartialEq)]
+struct RawTrackInfo {
+    track_id: Option<usize>,
+    title_link: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 struct RawStreamingUrl {
     #[serde(rename(deserialize = "mp3-128"))]
@@ -134,15 +158,24 @@ pub struct AlbumDetailsPayload<'a> {
 
 impl Bandcamp {
     pub const API_BASE_URL: &'static str = "https://bandcamp.com/api";
+    pub const SITE_BASE_URL: &'static str = "https://bandcamp.com";
     pub const IMAGE_API_BASE_URL: &'static str = "https://f4.bcbits.com/img";
 
-    pub async fn download(&self, client: &Client, path: &Path, filename: &str) -> Result<PathBuf> {
+    pub async fn download(
+        &self,
+        client: &Client,
+        config: &ServiceConfig,
+        path: &Path,
+        filename: &str,
+    ) -> Result<PathBuf> {
         let request = match &self.streaming_url {
-            Some(streaming_url) => client.get(streaming_url),
+            Some(streaming_url) => {
+                config.request_url(client, Method::GET, &config.media_url(streaming_url))
+            }
             None => return Err(Error::DownloadError("no streaming url".to_string())),
         };
 
-        let response = request.send().await?;
+        let response = config.send(request).await?;
 
         let mut full_path: PathBuf = path.to_owned();
         full_path.push(filename);
@@ -154,23 +187,30 @@ impl Bandcamp {
         Ok(full_path)
     }
 
-    async fn post(client: &Client, path: &str, body: &str) -> Result<Value> {
+    async fn post(
+        client: &Client,
+        config: &ServiceConfig,
+        path: &str,
+        body: &str,
+    ) -> Result<Value> {
         // let raw_payload: String = serde_json::to_string(&body)?;
 
-        let request: RequestBuilder = client
-            .post(format!("{}/{}", Self::API_BASE_URL, path))
+        let request: RequestBuilder = config
+            .request(client, Method::POST, path)
             .body(body.to_owned());
 
-        let mut response: Response = request.send().await?;
-        response = response.error_for_status()?;
+        let response: Response = config.send(request).await?;
 
-        let data: serde_json::Value = serde_json::from_str(&response.text().await?)?;
+        let body: String = response.text().await?;
+        let data: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|_| Error::unexpected_response("Bandcamp", path, &body))?;
 
         Ok(data)
     }
 
     async fn get_raw_results_from_search(
         client: &Client,
+        config: &ServiceConfig,
         query: &str,
     ) -> Result<Vec<RawTrackSearchResult>> {
         let payload = SearchPayload {
@@ -182,6 +222,7 @@ impl Bandcamp {
 
         let mut results = Self::post(
             client,
+            config,
             "bcsearch_public_api/1/autocomplete_elastic",
             &serde_json::to_string(&payload)?,
         )
@@ -193,122 +234,440 @@ impl Bandcamp {
         Ok(tracks)
     }
 
+    /// Details of the track (`tralbum_type` `t`) or album (`a`) `tralbum_id`
     async fn get_raw_album_from_id(
         client: &Client,
-        track_id: usize,
+        config: &ServiceConfig,
+        tralbum_id: usize,
         band_id: usize,
+        tralbum_type: &str,
     ) -> Result<RawAlbum> {
         let payload = AlbumDetailsPayload {
-            tralbum_id: track_id,
+            tralbum_id,
             band_id,
-            tralbum_type: "t",
+            tralbum_type,
         };
 
-        let mut results = Self::post(
+        let path: &str = "mobile/25/tralbum_details";
+        let results: Value =
+            Self::post(client, config, path, &serde_json::to_string(&payload)?).await?;
+
+        parse_response("Bandcamp", path, &results)
+    }
+
+    fn candidate_from_raw(raw_track_search_result: &RawTrackSearchResult) -> Candidate {
+        let album_name: &str = match &raw_track_search_result.album_name {
+            Some(a) => a,
+            None => {
+                // if no album name, assume it is a single and use song name in place"
+                &raw_track_search_result.name
+            }
+        };
+
+        // search results have no duration, so it isn't scored
+        Candidate {
+            id: raw_track_search_result.id.to_string(),
+            url: raw_track_search_result.item_url_path.to_owned(),
+            name: raw_track_search_result.name.to_owned(),
+            artists: vec![raw_track_search_result.band_name.to_owned()],
+            album: Some(album_name.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// Query searching for `track`'s metadata
+    fn search_query(track: &Track) -> Result<String> {
+        Ok(format!(
+            "{}, {}, {}",
+            search_title(&track.name),
+            search_artists(&track.artists)?,
+            search_title(&track.album),
+        ))
+    }
+
+    /// Tracks found by searching for `query`, ranked against `track`
+    async fn get_ranked_raw_results_from_search(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &Track,
+        query: &str,
+    ) -> Result<Vec<(MatchReport, RawTrackSearchResult)>> {
+        let lackluster_search_result: Vec<RawTrackSearchResult> =
+            Self::get_raw_results_from_search(client, config, query).await?;
+
+        Ok(config.matcher.rank(
+            track,
+            lackluster_search_result
+                .into_iter()
+                .map(|raw_track_search_result| {
+                    (
+                        Self::candidate_from_raw(&raw_track_search_result),
+                        raw_track_search_result,
+                    )
+                })
+                .collect(),
+        ))
+    }
+
+    /// Every track found by searching for `track`'s metadata, best match first
+    pub async fn search(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &Track,
+    ) -> Result<Vec<MatchReport>> {
+        Ok(Self::get_ranked_raw_results_from_search(
             client,
-            "mobile/25/tralbum_details",
-            &serde_json::to_string(&payload)?,
+            config,
+            track,
+            &Self::search_query(track)?,
+        )
+        .await?
+        .into_iter()
+        .map(|(report, _)| report)
+        .collect())
+    }
+
+    async fn get_raw_track_match_from_track(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &Track,
+    ) -> Result<(RawAlbum, MatchProvenance)> {
+        let query: String = Self::search_query(track)?;
+        let (report, raw_track_search_result): (MatchReport, RawTrackSearchResult) =
+            Matcher::best_match(
+                Self::get_ranked_raw_results_from_search(client, config, track, &query).await?,
+            )
+            .ok_or(Error::NotFound {
+                service: "Bandcamp".to_string(),
+                query: format!("{} - {}", track.name, track.artists.join(", ")),
+            })?;
+
+        let raw_album: RawAlbum = Self::get_raw_album_from_id(
+            client,
+            config,
+            raw_track_search_result.id,
+            raw_track_search_result.band_id,
+            "t",
         )
         .await?;
 
-        let album: RawAlbum = serde_json::from_value(results.take())?;
+        Ok((
+            raw_album,
+            MatchProvenance::from_report(MatchMethod::FuzzySearch, &report, &query),
+        ))
+    }
 
-        Ok(album)
+    pub async fn create_service_for_track(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &mut Track,
+    ) -> Result<()> {
+        let (raw_album, provenance): (RawAlbum, MatchProvenance) =
+            Self::get_raw_track_match_from_track(client, config, track).await?;
+        let raw_track: &RawAlbumTrack = raw_album.tracks.first().ok_or_else(|| {
+            Error::missing_field("Bandcamp", "mobile/25/tralbum_details", "tracks")
+        })?;
+        let service: Self = Self::create_service_from_raw(&raw_album, raw_track, provenance);
+        track.services.bandcamp = Some(service);
+        Ok(())
     }
 
-    async fn get_raw_track_match_from_track(client: &Client, track: &Track) -> Result<RawAlbum> {
-        let lackluster_search_result: Vec<RawTrackSearchResult> =
-            Self::get_raw_results_from_search(
-                client,
-                &format!(
-                    "{}, {}, {}",
-                    track.name,
-                    track.artists.get(0).ok_or(Error::TrackError(
-                        "Track requires at least one artist".to_string()
-                    ))?,
-                    track.album,
-                ),
-            )
-            .await?;
-
-        for raw_track_search_result in &lackluster_search_result {
-            let album_name: &str = match &raw_track_search_result.album_name {
-                Some(a) => a,
-                None => {
-                    // if their are multiple results, go to the next one which
-                    // might have an album attached to it.
-                    // if there are multiple tracks all with no albums, then
-                    // nothing gets chosen...
-                    //if lackluster_search_result.len() > 1 {
-                    //    continue;
-                    //}
-                    //""
-
-                    // if no album name, assume it is a single and use song name in place"
-                    &raw_track_search_result.name
+    /// Band id, tralbum id, tralbum type (`t` or `a`) and the page URL of each
+    /// track by id of the track or album page at `url`
+    async fn get_tralbum_ids_from_url(
+        client: &Client,
+        config: &ServiceConfig,
+        url: &str,
+    ) -> Result<(usize, usize, String, HashMap<usize, String>)> {
+        // band pages live on their own subdomain, so they're rebased like media
+        let request: RequestBuilder =
+            config.request_url(client, Method::GET, &config.media_url(url));
+        let response: Response = config.send(request).await?;
+        let raw_page: String = response.text().await?;
+        let track_urls: HashMap<usize, String> = Self::get_track_urls_from_page(&raw_page, url)?;
+        let page: String = raw_page.replace("&quot;", "\"");
+
+        let page_properties_re =
+            regex::Regex::new(r#""item_type":\s*"([ta])",\s*"item_id":\s*(\d+)"#)?;
+        let band_id_re = regex::Regex::new(r#""band_id":\s*(\d+)"#)?;
+
+        let not_found = || Error::NotFound {
+            service: "Bandcamp".to_string(),
+            query: url.to_owned(),
+        };
+        let page_properties = page_properties_re.captures(&page).ok_or_else(not_found)?;
+        let band_id: usize = band_id_re.captures(&page).ok_or_else(not_found)?[1].parse()?;
+
+        Ok((
+            band_id,
+            page_properties[2].parse()?,
+            page_properties[1].to_owned(),
+            track_urls,
+        ))
+    }
+
+    /// Page URL of each track listed in the `trackinfo` of the track or album
+    /// page at `url`. Links are relative to the band's subdomain.
+    fn get_track_urls_from_page(page: &str, url: &str) -> Result<HashMap<usize, String>> {
+        let tralbum_re = regex::Regex::new(r#"data-tralbum="([^"]*)""#)?;
+        let Some(tralbum) = tralbum_re.captures(page) else {
+            return Ok(HashMap::new());
+        };
+        let tralbum: Value =
+            serde_json::from_str(&tralbum[1].replace("&quot;", "\"").replace("&amp;", "&"))?;
+        let track_info: Vec<RawTrackInfo> = match tralbum.get("trackinfo") {
+            Some(track_info) => parse_response("Bandcamp", url, track_info)?,
+            None => return Ok(HashMap::new()),
+        };
+
+        let band_url: String = url.split('/').take(3).collect::<Vec<&str>>().join("/");
+        Ok(track_info
+            .into_iter()
+            .filter_map(|info| match (info.track_id, info.title_link) {
+                (Some(track_id), Some(link)) if link.starts_with('/') => {
+                    Some((track_id, format!("{}{}", band_url, link)))
                 }
-            };
+                (Some(track_id), Some(link)) => Some((track_id, link)),
+                _ => None,
+            })
+            .collect())
+    }
 
-            if track.compare_similarity_fuzzy(
-                &raw_track_search_result.name,
-                &raw_track_search_result.band_name,
-                album_name,
-                0,
-            ) >= 3.0
-            {
-                let raw_album: RawAlbum = Self::get_raw_album_from_id(
-                    client,
-                    raw_track_search_result.id,
-                    raw_track_search_result.band_id,
-                )
-                .await?;
-
-                return Ok(raw_album);
-            }
+    async fn get_raw_album_from_url(
+        client: &Client,
+        config: &ServiceConfig,
+        url: &str,
+        tralbum_type: &str,
+    ) -> Result<RawAlbum> {
+        let (band_id, tralbum_id, page_type, track_urls) =
+            Self::get_tralbum_ids_from_url(client, config, url).await?;
+        if page_type != tralbum_type {
+            return Err(Error::NotFound {
+                service: "Bandcamp".to_string(),
+                query: format!(
+                    "{} {}",
+                    match tralbum_type {
+                        "t" => "track",
+                        _ => "album",
+                    },
+                    url
+                ),
+            });
         }
+        let mut raw_album: RawAlbum =
+            Self::get_raw_album_from_id(client, config, tralbum_id, band_id, tralbum_type).await?;
+        raw_album.track_urls = track_urls;
+        Ok(raw_album)
+    }
+
+    /// The track page at `url`, as the track it's the source of. Bandcamp has no
+    /// ISRCs.
+    pub async fn create_track_from_url(
+        client: &Client,
+        config: &ServiceConfig,
+        url: &str,
+    ) -> Result<Track> {
+        let raw_album: RawAlbum = Self::get_raw_album_from_url(client, config, url, "t").await?;
+        let raw_track: &RawAlbumTrack = raw_album.tracks.first().ok_or_else(|| {
+            Error::missing_field("Bandcamp", "mobile/25/tralbum_details", "tracks")
+        })?;
+        Ok(Self::create_track_from_raw(&raw_album, raw_track))
+    }
 
-        Err(Error::TrackError("no match found".to_string()))
+    /// The album page at `url` with every track on it
+    pub async fn create_album_from_url(
+        client: &Client,
+        config: &ServiceConfig,
+        url: &str,
+    ) -> Result<album::Album> {
+        let raw_album: RawAlbum = Self::get_raw_album_from_url(client, config, url, "a").await?;
+        let (release_year, release_month, release_day) =
+            date_from_timestamp(raw_album.release_date as u64);
+
+        Ok(album::Album {
+            name: raw_album.title.to_owned(),
+            tracks: raw_album
+                .tracks
+                .iter()
+                .map(|raw_track| Self::create_track_from_raw(&raw_album, raw_track))
+                .collect(),
+            id: raw_album.id.to_string(),
+            source_service: Source::Bandcamp,
+            release_year,
+            release_month: Some(release_month),
+            release_day: Some(release_day),
+            artists: vec![raw_album.tralbum_artist.to_owned()],
+            upc: None,
+            label: raw_album.label.to_owned(),
+        })
     }
 
-    pub async fn create_service_for_track(client: &Client, track: &mut Track) -> Result<()> {
-        let raw_album: RawAlbum = Self::get_raw_track_match_from_track(client, track).await?;
-        let service: Self = Self::create_service_from_raw(&raw_album).await?;
-        track.services.bandcamp = Some(service);
-        Ok(())
+    fn create_track_from_raw(raw_album: &RawAlbum, raw_track: &RawAlbumTrack) -> Track {
+        let (release_year, release_month, release_day) =
+            date_from_timestamp(raw_album.release_date as u64);
+        let service: Self = Self::create_service_from_raw(
+            raw_album,
+            raw_track,
+            MatchProvenance::direct_id(&raw_track.track_id.to_string()),
+        );
+
+        Track {
+            name: raw_track.title.to_owned(),
+            album: service.album.name.to_owned(),
+            disk_number: 1,
+            track_number: raw_track.track_num.unwrap_or(1),
+            artists: vec![raw_track.band_name.to_owned()],
+            release_year,
+            release_month: Some(release_month),
+            release_day: Some(release_day),
+            is_explicit: false,
+            duration_ms: service.duration_ms,
+            isrc: None,
+            services: Services {
+                bandcamp: Some(service),
+                ..Default::default()
+            },
+            source_service: Source::Bandcamp,
+        }
     }
 
-    async fn create_service_from_raw(raw_album: &RawAlbum) -> Result<Self> {
-        let track: &RawAlbumTrack = raw_album.tracks.get(0).ok_or(Error::DatabaseError(
-            "no track in Bandcamp response".to_string(),
-        ))?;
-        let track_url_split = raw_album.bandcamp_url.split("/");
+    /// Record of `track` on the track or album `raw_album`
+    fn create_service_from_raw(
+        raw_album: &RawAlbum,
+        track: &RawAlbumTrack,
+        provenance: MatchProvenance,
+    ) -> Self {
+        let track_url_split = raw_album.bandcamp_url.split('/');
         let artist_url: String = track_url_split.collect::<Vec<&str>>()[0..3].join("/");
-        let album_url: String =
-            format!("https://bandcamp.com/EmbeddedPlayer/album={}", raw_album.id);
-        return Ok(Bandcamp {
+        // a track's details name the album it's on, an album's are the album itself
+        let (album_id, album_name, total_tracks): (usize, &str, Option<usize>) =
+            match (raw_album.album_id, &raw_album.album_title) {
+                (Some(album_id), Some(album_title)) => (album_id, album_title, None),
+                _ => match raw_album.r#type.as_str() {
+                    "a" => (raw_album.id, &raw_album.title, Some(raw_album.tracks.len())),
+                    // a single, named after its track
+                    _ => (raw_album.id, &raw_album.title, Some(1)),
+                },
+            };
+        let album_url: String = format!("https://bandcamp.com/EmbeddedPlayer/album={}", album_id);
+        Bandcamp {
             id: track.track_id.to_string(),
             name: track.title.to_owned(),
-            url: raw_album.bandcamp_url.to_owned(),
+            // tracks on an album page link to their own page, unless unlisted
+            url: raw_album
+                .track_urls
+                .get(&track.track_id)
+                .unwrap_or(&raw_album.bandcamp_url)
+                .to_owned(),
             artists: vec![Artist {
                 id: raw_album.band.band_id.to_string(),
                 name: raw_album.band.name.to_owned(),
                 url: artist_url,
             }],
             album: Album {
-                id: raw_album.id.to_string(),
-                name: raw_album.title.to_owned(),
+                id: album_id.to_string(),
+                name: album_name.to_owned(),
                 url: album_url,
-                total_tracks: None,
+                total_tracks,
                 ean: None,
                 upc: None,
             },
             image: format!("{}/a{}_0.jpg", Self::IMAGE_API_BASE_URL, raw_album.art_id),
-            streaming_url: match &track.streaming_url {
-                Some(url) => Some(url.mp3_128.to_owned()),
-                None => None,
-            },
+            streaming_url: track
+                .streaming_url
+                .as_ref()
+                .map(|url| url.mp3_128.to_owned()),
             duration_ms: (track.duration * 1000_f64) as usize,
-        });
+            provenance: Some(provenance),
+        }
+    }
+}
+
+impl BandcampProvider {
+    pub fn new() -> Self {
+        Self::with_config(ServiceConfig::bandcamp())
+    }
+
+    pub fn with_config(config: ServiceConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Default for BandcampProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MusicService for BandcampProvider {
+    fn name(&self) -> &str {
+        "Bandcamp"
+    }
+
+    fn source(&self) -> Source {
+        Source::Bandcamp
+    }
+
+    fn storefront(&self) -> Option<&str> {
+        Some(&self.config.storefront)
+    }
+
+    fn service_record(&self, track: &Track) -> Option<Value> {
+        serde_json::to_value(track.services.bandcamp.as_ref()?).ok()
+    }
+
+    fn set_service_record(&self, track: &mut Track, record: Value) -> Result<()> {
+        track.services.bandcamp = Some(serde_json::from_value(record)?);
+        Ok(())
+    }
+
+    /// `id` is the URL of the track page
+    async fn create_track_from_id(&self, client: &Client, id: &str) -> Result<Track> {
+        Bandcamp::create_track_from_url(client, &self.config, id).await
+    }
+
+    /// `id` is the URL of the album page
+    async fn create_album_from_id(&self, client: &Client, id: &str) -> Result<album::Album> {
+        Bandcamp::create_album_from_url(client, &self.config, id).await
+    }
+
+    async fn create_service_from_id(
+        &self,
+        client: &Client,
+        track: &mut Track,
+        id: &str,
+    ) -> Result<()> {
+        track.services.bandcamp = Bandcamp::create_track_from_url(client, &self.config, id)
+            .await?
+            .services
+            .bandcamp;
+        Ok(())
+    }
+
+    async fn search(&self, client: &Client, track: &Track) -> Result<Vec<MatchReport>> {
+        Bandcamp::search(client, &self.config, track).await
+    }
+
+    async fn create_service_for_track(&self, client: &Client, track: &mut Track) -> Result<()> {
+        Bandcamp::create_service_for_track(client, &self.config, track).await
+    }
+
+    async fn download(
+        &self,
+        client: &Client,
+        track: &Track,
+        path: &Path,
+        filename: &str,
+    ) -> Result<PathBuf> {
+        match &track.services.bandcamp {
+            Some(bandcamp) => {
+                bandcamp
+                    .download(client, &self.config, path, filename)
+                    .await
+            }
+            None => Err(Error::DownloadError("no Bandcamp service".to_string())),
+        }
     }
 }
 
@@ -316,18 +675,17 @@ impl Bandcamp {
 mod tests {
 
     use crate::{
+        album::Album,
+        bandcamp::BandcampProvider,
+        config::ServiceConfig,
+        replay::ReplayServer,
         service::{Services, Source},
         track::Track,
     };
 
     #[tokio::test]
     async fn get_match() {
-        let example_services: Services = Services {
-            spotify: None,
-            apple_music: None,
-            youtube: None,
-            bandcamp: None,
-        };
+        let example_services: Services = Services::default();
 
         let mut example_track: Track = Track {
             name: "Duchess for Nothing".to_owned(),
@@ -349,7 +707,75 @@ mod tests {
                     .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15")
                     .build()
                     .unwrap();
+        let server: ReplayServer =
+            ReplayServer::start("bandcamp/get_match", ServiceConfig::bandcamp()).await;
+
+        example_track
+            .add_service(&client, &BandcampProvider::with_config(server.config()))
+            .await
+            .unwrap();
+
+        let bandcamp = example_track.services.bandcamp.unwrap();
+        assert_eq!(bandcamp.id, "2812345678");
+        assert_eq!(bandcamp.artists[0].url, "https://tunabunny.bandcamp.com");
+        assert_eq!(bandcamp.duration_ms, 138026);
+        assert_eq!(bandcamp.album.name, "Genius Fatigue");
+    }
 
-        example_track.add_bandcamp(&client).await.unwrap();
+    #[tokio::test]
+    async fn creates_track_from_url() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("bandcamp/track", ServiceConfig::bandcamp()).await;
+
+        let track: Track = Track::from_service_id(
+            &client,
+            &BandcampProvider::with_config(server.config()),
+            "https://tunabunny.bandcamp.com/track/duchess-for-nothing",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(track.name, "Duchess for Nothing");
+        assert_eq!(track.album, "Genius Fatigue");
+        assert_eq!(track.artists, vec!["Tunabunny"]);
+        assert_eq!(
+            (track.release_year, track.release_month, track.release_day),
+            (2013, Some(2), Some(19))
+        );
+        assert_eq!(track.duration_ms, 138026);
+        assert_eq!(track.source_service, Source::Bandcamp);
+        assert_eq!(track.services.bandcamp.unwrap().album.id, "3101234567");
+    }
+
+    #[tokio::test]
+    async fn creates_album_from_url() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("bandcamp/album", ServiceConfig::bandcamp()).await;
+
+        let album: Album = Album::from_service_id(
+            &client,
+            &BandcampProvider::with_config(server.config()),
+            "https://tunabunny.bandcamp.com/album/genius-fatigue",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(album.name, "Genius Fatigue");
+        assert_eq!(album.id, "3101234567");
+        assert_eq!(album.label.as_deref(), Some("HHBTM Records"));
+        assert_eq!(album.release_year, 2013);
+        assert_eq!(album.tracks.len(), 2);
+        assert_eq!(album.tracks[1].name, "Bad Ideas");
+        assert_eq!(album.tracks[1].track_number, 2);
+        let bandcamp = album.tracks[1].services.bandcamp.as_ref().unwrap();
+        assert_eq!(bandcamp.id, "2812345679");
+        assert_eq!(
+            bandcamp.url,
+            "https://tunabunny.bandcamp.com/track/bad-ideas"
+        );
+        assert_eq!(album.tracks[1].artists, vec!["Tunabunny"]);
+        assert_eq!(bandcamp.album.total_tracks, Some(2));
     }
 }
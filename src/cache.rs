@@ -0,0 +1,509 @@
+//! Persistent cache of matched service records.
+//!
+//! Searching every service for every track is slow and burns through rate
+//! limits, so records found by `MusicService::create_service_for_track` are
+//! kept in a JSON file (by default in the XDG cache directory) keyed by the
+//! service name, its storefront and the track's ISRC or normalized metadata.
+//! Wrapping a service
+//! in `CachedService` makes `Track`, `Playlist` and `Album` consult the cache
+//! before hitting the network. Their `add_*` helpers do this with
+//! `MatchCache::shared_default`.
+
+use crate::album;
+use crate::error::Result;
+use crate::matcher::MatchReport;
+use crate::normalize::{artist_keys, split_title};
+use crate::playlist::Playlist;
+use crate::service::{MusicService, Source};
+use crate::track::Track;
+use crate::utils::cache_dir;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct CacheEntry {
+    /// Seconds since the Unix epoch
+    cached_at: u64,
+    record: Value,
+}
+
+/// Service records keyed by `{service}:{storefront}:isrc:{isrc}` and
+/// `{service}:{storefront}:meta:{name}|{artists}|{album}|{duration in seconds}`.
+/// Entries inserted since the last `save` are written when the cache is dropped.
+#[derive(Debug)]
+pub struct MatchCache {
+    path: PathBuf,
+    ttl: Duration,
+    entries: Mutex<BTreeMap<String, CacheEntry>>,
+    /// Entries were inserted since the last `save`
+    unsaved: AtomicBool,
+}
+
+impl MatchCache {
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+    /// `matches.json` in `utils::cache_dir`
+    pub fn default_path() -> Option<PathBuf> {
+        Some(cache_dir()?.join("matches.json"))
+    }
+
+    /// Load the cache at `path`. A missing or unreadable file starts an empty cache.
+    pub fn open(path: &Path) -> Self {
+        let entries: BTreeMap<String, CacheEntry> = match std::fs::read_to_string(path) {
+            Ok(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+                log::warn!(
+                    "\tIgnoring invalid match cache {}: {}",
+                    path.to_string_lossy(),
+                    e
+                );
+                BTreeMap::new()
+            }),
+            Err(..) => BTreeMap::new(),
+        };
+
+        MatchCache {
+            path: path.to_owned(),
+            ttl: Self::DEFAULT_TTL,
+            entries: Mutex::new(entries),
+            unsaved: AtomicBool::new(false),
+        }
+    }
+
+    /// The cache at `default_path`, loaded on first use and shared by every later
+    /// caller. `None` without a cache directory.
+    pub fn shared_default() -> Option<Arc<Self>> {
+        static SHARED: OnceLock<Option<Arc<MatchCache>>> = OnceLock::new();
+        SHARED
+            .get_or_init(|| match Self::default_path() {
+                Some(path) => Some(Arc::new(Self::open(&path))),
+                None => {
+                    log::warn!("Unable to find cache directory, not caching matches");
+                    None
+                }
+            })
+            .clone()
+    }
+
+    /// Entries older than `ttl` are ignored and dropped on the next `save`
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Cached record of `service` in `storefront` for `track`, by ISRC first and
+    /// metadata second
+    pub fn get(&self, service: &str, storefront: Option<&str>, track: &Track) -> Option<Value> {
+        let entries = self.entries.lock().unwrap();
+        let now: u64 = now();
+        cache_keys(service, storefront, track)
+            .iter()
+            .find_map(|key| {
+                entries
+                    .get(key)
+                    .filter(|entry| !self.is_expired(entry, now))
+                    .map(|entry| entry.record.clone())
+            })
+    }
+
+    /// Remember `record` as the match of `service` in `storefront` for `track`
+    pub fn insert(&self, service: &str, storefront: Option<&str>, track: &Track, record: Value) {
+        let mut entries = self.entries.lock().unwrap();
+        let cached_at: u64 = now();
+        self.unsaved.store(true, Ordering::Relaxed);
+        for key in cache_keys(service, storefront, track) {
+            entries.insert(
+                key,
+                CacheEntry {
+                    cached_at,
+                    record: record.clone(),
+                },
+            );
+        }
+    }
+
+    /// Write the cache to `path`, dropping expired entries
+    pub fn save(&self) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let now: u64 = now();
+        entries.retain(|_, entry| !self.is_expired(entry, now));
+        self.unsaved.store(false, Ordering::Relaxed);
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string(&*entries)?)?;
+        Ok(())
+    }
+
+    /// `save` if entries were inserted since the last one, only logging failures.
+    /// Shared caches are never dropped, so their users call this instead.
+    pub fn flush(&self) {
+        if !self.unsaved.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = self.save() {
+            log::warn!(
+                "\tUnable to save match cache {}: {}",
+                self.path.to_string_lossy(),
+                e
+            );
+        }
+    }
+
+    /// Forget every entry and delete the cache file
+    pub fn clear(&self) -> Result<()> {
+        self.entries.lock().unwrap().clear();
+        self.unsaved.store(false, Ordering::Relaxed);
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_expired(&self, entry: &CacheEntry, now: u64) -> bool {
+        now.saturating_sub(entry.cached_at) >= self.ttl.as_secs()
+    }
+}
+
+impl Drop for MatchCache {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// `MusicService` that answers `create_service_for_track(s)` from a `MatchCache`
+/// when it can, and stores whatever `service` finds otherwise
+pub struct CachedService {
+    service: Box<dyn MusicService>,
+    cache: Arc<MatchCache>,
+}
+
+impl CachedService {
+    pub fn new(service: Box<dyn MusicService>, cache: Arc<MatchCache>) -> Self {
+        CachedService { service, cache }
+    }
+
+    /// Fill `track` from the cache, returning whether it was found
+    fn add_cached(&self, track: &mut Track) -> bool {
+        let record: Value =
+            match self
+                .cache
+                .get(self.service.name(), self.service.storefront(), track)
+            {
+                Some(record) => record,
+                None => return false,
+            };
+
+        match self.service.set_service_record(track, record) {
+            Ok(..) => true,
+            Err(e) => {
+                log::debug!("Ignoring cached {} record: {}", self.service.name(), e);
+                false
+            }
+        }
+    }
+
+    /// Remember the records of `tracks`. They're written when the cache is
+    /// saved or dropped.
+    fn store(&self, tracks: &[Track]) {
+        for track in tracks {
+            if let Some(record) = self.service.service_record(track) {
+                self.cache.insert(
+                    self.service.name(),
+                    self.service.storefront(),
+                    track,
+                    record,
+                );
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MusicService for CachedService {
+    fn name(&self) -> &str {
+        self.service.name()
+    }
+
+    fn source(&self) -> Source {
+        self.service.source()
+    }
+
+    fn storefront(&self) -> Option<&str> {
+        self.service.storefront()
+    }
+
+    fn service_record(&self, track: &Track) -> Option<Value> {
+        self.service.service_record(track)
+    }
+
+    fn set_service_record(&self, track: &mut Track, record: Value) -> Result<()> {
+        self.service.set_service_record(track, record)
+    }
+
+    async fn create_track_from_id(&self, client: &Client, id: &str) -> Result<Track> {
+        self.service.create_track_from_id(client, id).await
+    }
+
+    async fn create_playlist_from_id(&self, client: &Client, id: &str) -> Result<Playlist> {
+        self.service.create_playlist_from_id(client, id).await
+    }
+
+    async fn create_album_from_id(&self, client: &Client, id: &str) -> Result<album::Album> {
+        self.service.create_album_from_id(client, id).await
+    }
+
+    async fn search(&self, client: &Client, track: &Track) -> Result<Vec<MatchReport>> {
+        self.service.search(client, track).await
+    }
+
+    async fn create_service_from_id(
+        &self,
+        client: &Client,
+        track: &mut Track,
+        id: &str,
+    ) -> Result<()> {
+        self.service
+            .create_service_from_id(client, track, id)
+            .await?;
+        self.store(std::slice::from_ref(track));
+        Ok(())
+    }
+
+    async fn create_service_for_track(&self, client: &Client, track: &mut Track) -> Result<()> {
+        if self.add_cached(track) {
+            return Ok(());
+        }
+
+        self.service.create_service_for_track(client, track).await?;
+        self.store(std::slice::from_ref(track));
+        Ok(())
+    }
+
+    async fn create_services_for_tracks(
+        &self,
+        client: &Client,
+        tracks: &mut [Track],
+    ) -> Result<()> {
+        let uncached: Vec<usize> = tracks
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, track)| (!self.add_cached(track)).then_some(i))
+            .collect();
+
+        log::debug!(
+            "{} of {} tracks found in {} match cache",
+            tracks.len() - uncached.len(),
+            tracks.len(),
+            self.service.name()
+        );
+
+        if uncached.is_empty() {
+            return Ok(());
+        }
+
+        // move the uncached tracks to the front (keeping their order) so the
+        // service can still batch them, then swap everything back
+        for (i, j) in uncached.iter().enumerate() {
+            tracks.swap(i, *j);
+        }
+        let result: Result<()> = self
+            .service
+            .create_services_for_tracks(client, &mut tracks[..uncached.len()])
+            .await;
+        self.store(&tracks[..uncached.len()]);
+        self.cache.flush();
+        for (i, j) in uncached.iter().enumerate().rev() {
+            tracks.swap(i, *j);
+        }
+
+        result
+    }
+
+    async fn download(
+        &self,
+        client: &Client,
+        track: &Track,
+        path: &Path,
+        filename: &str,
+    ) -> Result<PathBuf> {
+        self.service.download(client, track, path, filename).await
+    }
+}
+
+fn cache_keys(service: &str, storefront: Option<&str>, track: &Track) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::with_capacity(2);
+    let service: String = format!("{}:{}", service, storefront.unwrap_or_default());
+
+    if let Some(isrc) = &track.isrc {
+        keys.push(format!("{}:isrc:{}", service, isrc.trim().to_uppercase()));
+    }
+
+    // every artist, in any order, so featured artists tell tracks apart
+    let mut artists: Vec<String> =
+        artist_keys(&track.artists, &split_title(&track.name).featured_artists);
+    artists.sort();
+
+    keys.push(format!(
+        "{}:meta:{}|{}|{}|{}",
+        service,
+        normalize(&track.name),
+        artists.join(","),
+        normalize(&track.album),
+        (track.duration_ms + 500) / 1000
+    ));
+
+    keys
+}
+
+/// Lowercase with runs of whitespace collapsed
+fn normalize(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        cache::{CachedService, MatchCache},
+        config::ServiceConfig,
+        replay::ReplayServer,
+        request::RequestPolicy,
+        service::{Services, Source},
+        spotify::SpotifyProvider,
+        track::Track,
+    };
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn example_track(isrc: Option<&str>) -> Track {
+        Track {
+            name: "Duchess for Nothing".to_owned(),
+            album: "Genius Fatigue".to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_owned()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            services: Services::default(),
+            isrc: isrc.map(str::to_owned),
+            source_service: Source::Spotify,
+        }
+    }
+
+    fn cache_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("songvert-{}-{}.json", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn second_lookup_is_served_from_cache() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let path: PathBuf = cache_path("second_lookup_is_served_from_cache");
+
+        let server: ReplayServer =
+            ReplayServer::start("spotify/get_match_with_isrc", ServiceConfig::spotify()).await;
+        let spotify = CachedService::new(
+            Box::new(SpotifyProvider::with_config(
+                &server.auth("SPOTIFY_TOKEN"),
+                server.config(),
+            )),
+            Arc::new(MatchCache::open(&path)),
+        );
+        let mut first: Track = example_track(Some("USZUD1215001"));
+        first.add_service(&client, &spotify).await.unwrap();
+        // single tracks are written to the file when the cache is dropped
+        drop(spotify);
+
+        // nothing is listening here, so only the cache can answer
+        let offline = CachedService::new(
+            Box::new(SpotifyProvider::with_config(
+                "replay",
+                ServiceConfig::spotify()
+                    .with_base_url("http://127.0.0.1:9")
+                    .with_request_policy(RequestPolicy {
+                        max_retries: 0,
+                        ..Default::default()
+                    }),
+            )),
+            Arc::new(MatchCache::open(&path)),
+        );
+        let mut second: Track = example_track(None);
+        second.add_service(&client, &offline).await.unwrap();
+
+        assert_eq!(second.services.spotify, first.services.spotify);
+        assert_eq!(
+            second.services.spotify.unwrap().id,
+            "6Jd9HwGbIUVjQpHwQ0R9yL"
+        );
+
+        MatchCache::open(&path).clear().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn expired_entries_are_ignored() {
+        let path: PathBuf = cache_path("expired_entries_are_ignored");
+        let track: Track = example_track(Some("USZUD1215001"));
+
+        let cache: MatchCache = MatchCache::open(&path);
+        cache.insert(
+            "Spotify",
+            Some("us"),
+            &track,
+            serde_json::json!({"id": "6Jd9HwGbIUVjQpHwQ0R9yL"}),
+        );
+        assert!(cache.get("Spotify", Some("us"), &track).is_some());
+        assert!(cache.get("Spotify", Some("gb"), &track).is_none());
+        assert!(cache.get("Apple Music", Some("us"), &track).is_none());
+
+        let cache: MatchCache = cache.with_ttl(Duration::ZERO);
+        assert!(cache.get("Spotify", Some("us"), &track).is_none());
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn metadata_keys_include_every_artist() {
+        let path: PathBuf = cache_path("metadata_keys_include_every_artist");
+        let track: Track = example_track(None);
+
+        let cache: MatchCache = MatchCache::open(&path);
+        cache.insert(
+            "Spotify",
+            Some("us"),
+            &track,
+            serde_json::json!({"id": "6Jd9HwGbIUVjQpHwQ0R9yL"}),
+        );
+
+        // same title and album, but another artist
+        let mut other: Track = example_track(None);
+        other.artists.push("Of Montreal".to_owned());
+        assert!(cache.get("Spotify", Some("us"), &other).is_none());
+        assert!(cache.get("Spotify", Some("us"), &track).is_some());
+        cache.clear().unwrap();
+    }
+}
@@ -1,15 +1,33 @@
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand};
 use reqwest::Client;
 use songvert::{
-    apple_music::*, bandcamp::*, error::*, playlist::*, service::*, spotify::*, track::*,
-    youtube::*,
+    album::Album, apple_music::*, auth::*, bandcamp::*, cache::*, config::*, deezer::*, error::*,
+    matcher::ExplicitPreference, overrides::*, playlist::*, request::*, service::*, spotify::*,
+    track::*, utils::cache_dir, youtube::*,
 };
 use std::path::PathBuf;
+use std::sync::Arc;
 
-/// Easily convert URLs between Spotify, Apple Music, Bandcamp, and YouTube
+/// Easily convert URLs between Spotify, Apple Music, Bandcamp, YouTube, and Deezer
 #[derive(Parser, Debug)]
-#[command(name = "Songvert", version)]
+#[command(
+    name = "Songvert",
+    version,
+    subcommand_negates_reqs = true,
+    after_help = "Exit codes:
+  1  other error
+  2  invalid arguments
+  3  no match found
+  4  rate limited
+  5  not logged in or token rejected
+  6  unexpected response from a service
+  7  external tool (e.g. yt-dlp) missing
+  8  network error"
+)]
 pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[command(flatten)]
     input: SourceInput,
 
@@ -34,11 +52,174 @@ pub struct Cli {
 
     /// Spotify Access Token
     #[arg(long = "ST", value_name = "TOKEN")]
-    spotify_access_token: Option<PathBuf>,
+    spotify_access_token: Option<String>,
+
+    /// Spotify app Client ID, uses the login from `songvert login spotify` unless
+    /// --spotify-client-secret is also given
+    #[arg(long, value_name = "ID")]
+    spotify_client_id: Option<String>,
+
+    /// Spotify app Client Secret, for app-only (Client Credentials) access
+    #[arg(long, value_name = "SECRET", requires = "spotify_client_id")]
+    spotify_client_secret: Option<String>,
 
     /// Apple Music Bearer Token
     #[arg(long = "AT", value_name = "TOKEN")]
-    apple_music_bearer_token: Option<PathBuf>,
+    apple_music_bearer_token: Option<String>,
+
+    /// Apple Developer Team ID, for minting Apple Music tokens
+    #[arg(long, value_name = "ID", requires_all = ["apple_key_id", "apple_private_key"])]
+    apple_team_id: Option<String>,
+
+    /// MusicKit Key ID, for minting Apple Music tokens
+    #[arg(long, value_name = "ID", requires = "apple_team_id")]
+    apple_key_id: Option<String>,
+
+    /// MusicKit private key (.p8), for minting Apple Music tokens
+    #[arg(long, value_name = "FILE", requires = "apple_team_id")]
+    apple_private_key: Option<PathBuf>,
+
+    #[command(flatten)]
+    request_policy: RequestPolicyArgs,
+
+    /// Always search services, ignoring and not updating the match cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Also print the top N search results of each service for every track, to
+    /// find the right one when a match is wrong
+    #[arg(long, value_name = "N")]
+    candidates: Option<usize>,
+
+    /// Country whose catalogs to search, e.g. `gb`. Defaults to the country of
+    /// an Apple Music input URL, then `us`.
+    #[arg(long, value_name = "CC")]
+    region: Option<String>,
+
+    /// Countries to search, in order, when a track isn't found in --region
+    #[arg(long, value_name = "CC", value_delimiter = ',')]
+    fallback_regions: Vec<String>,
+
+    /// Which version to convert to when a service has both an explicit and a
+    /// clean one
+    #[arg(long, value_enum, default_value_t = Prefer::Same)]
+    prefer: Prefer,
+
+    /// Match overrides file, defaults to overrides.json in the config directory
+    #[arg(long, value_name = "FILE")]
+    overrides: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage the match cache
+    #[command(subcommand)]
+    Cache(CacheCommand),
+
+    /// Log in to a service
+    #[command(subcommand)]
+    Login(LoginCommand),
+
+    /// Manage match overrides
+    #[command(subcommand)]
+    Override(OverrideCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum OverrideCommand {
+    /// Always match the track at SOURCE to the track at TARGET
+    Add {
+        /// Spotify, Apple Music, YouTube, Bandcamp or Deezer track URL, or isrc:<ISRC>
+        source: String,
+
+        /// Spotify, Apple Music, YouTube, Bandcamp or Deezer track URL
+        target: String,
+    },
+
+    /// Never add SERVICE to the track at SOURCE
+    Skip {
+        /// Spotify, Apple Music, YouTube, Bandcamp or Deezer track URL, or isrc:<ISRC>
+        source: String,
+
+        /// Service to skip
+        #[arg(value_enum)]
+        service: OverrideService,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Prefer {
+    /// Same as the input track
+    Same,
+    Clean,
+    Explicit,
+}
+
+impl From<Prefer> for ExplicitPreference {
+    fn from(prefer: Prefer) -> Self {
+        match prefer {
+            Prefer::Same => ExplicitPreference::Same,
+            Prefer::Clean => ExplicitPreference::Clean,
+            Prefer::Explicit => ExplicitPreference::Explicit,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum OverrideService {
+    Spotify,
+    AppleMusic,
+    Bandcamp,
+    Youtube,
+    Deezer,
+}
+
+impl OverrideService {
+    /// `MusicService::name` of the service
+    fn name(&self) -> &'static str {
+        match self {
+            OverrideService::Spotify => "Spotify",
+            OverrideService::AppleMusic => "Apple Music",
+            OverrideService::Bandcamp => "Bandcamp",
+            OverrideService::Youtube => "YouTube",
+            OverrideService::Deezer => "Deezer",
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum LoginCommand {
+    /// Log in with a Spotify account (Authorization Code with PKCE)
+    Spotify {
+        /// Client ID of your Spotify app
+        #[arg(long, value_name = "ID")]
+        client_id: String,
+
+        /// Redirect URI registered for your Spotify app, must point at this machine
+        #[arg(long, value_name = "URI", default_value = SpotifyPkce::DEFAULT_REDIRECT_URI)]
+        redirect_uri: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Delete every cached match
+    Clear,
+}
+
+#[derive(Args, Debug)]
+struct RequestPolicyArgs {
+    /// Maximum requests in flight per service
+    #[arg(long, value_name = "N", default_value_t = RequestPolicy::default().max_concurrent_requests)]
+    max_concurrent_requests: usize,
+
+    /// Maximum requests started per second per service
+    #[arg(long, value_name = "N")]
+    requests_per_second: Option<f64>,
+
+    /// Retries after a rate limit or server error
+    #[arg(long, value_name = "N", default_value_t = RequestPolicy::default().max_retries)]
+    max_retries: u32,
 }
 
 #[derive(Args, Debug)]
@@ -75,6 +256,10 @@ struct ConversionServices {
     /// Output YouTube URL(s)
     #[arg(short = 'Y', long)]
     youtube: bool,
+
+    /// Output Deezer URL(s)
+    #[arg(short = 'D', long)]
+    deezer: bool,
 }
 
 impl Cli {
@@ -91,73 +276,134 @@ impl Cli {
             .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15")
             .build()?;
 
-        let spotify_access_token: Option<String> = match &self.spotify_access_token {
-            Some(token) => Some(token.to_string_lossy().into_owned()),
-            None => None,
-        };
+        match &self.command {
+            Some(Command::Cache(CacheCommand::Clear)) => {
+                return match MatchCache::default_path() {
+                    Some(path) => {
+                        MatchCache::open(&path).clear()?;
+                        log::info!("Cleared match cache {}", path.to_string_lossy());
+                        Ok(())
+                    }
+                    None => Err(Error::DatabaseError(
+                        "Unable to find cache directory".to_string(),
+                    )),
+                };
+            }
+            Some(Command::Login(LoginCommand::Spotify {
+                client_id,
+                redirect_uri,
+            })) => {
+                let mut pkce = SpotifyPkce::new(client_id, redirect_uri);
+                if let Some(path) = token_cache_file("spotify_user", client_id) {
+                    pkce = pkce.with_cache_file(&path);
+                }
+                pkce.login(&client, &SpotifyPkce::DEFAULT_SCOPES, |url| {
+                    println!("Open this URL to log in to Spotify:\n{}", url)
+                })
+                .await?;
+                log::info!("Logged in to Spotify");
+                return Ok(());
+            }
+            Some(Command::Override(command)) => {
+                let mut overrides = Overrides::open(&self.overrides_path()?)?;
+                let (source, service, value) = match command {
+                    OverrideCommand::Add { source, target } => {
+                        let target_info = get_target_info_from_track_url(target)?;
+                        (
+                            source,
+                            target_info.service,
+                            Override::Id(target_info.id.to_owned()),
+                        )
+                    }
+                    OverrideCommand::Skip { source, service } => {
+                        (source, service.name().to_owned(), Override::Skip)
+                    }
+                };
+                let source_key = get_override_key(source)?;
+                overrides.insert(&source_key, &service, value);
+                overrides.save()?;
+                log::info!(
+                    "Overrode {} for {} in {}",
+                    service,
+                    source_key,
+                    overrides.path().to_string_lossy()
+                );
+                return Ok(());
+            }
+            None => (),
+        }
+        let mut config = Config::default()
+            .with_request_policy(RequestPolicy {
+                max_concurrent_requests: self.request_policy.max_concurrent_requests,
+                requests_per_second: self.request_policy.requests_per_second,
+                max_retries: self.request_policy.max_retries,
+                ..Default::default()
+            })
+            .with_explicit_preference(self.prefer.into());
+        if let Some(region) = self.region() {
+            log::debug!("Using region {}", region);
+            config = config.with_storefront(region);
+        }
+        config = config.with_fallback_storefronts(&self.fallback_regions);
+        let cache = self.match_cache();
+        let overrides = Arc::new(self.match_overrides()?);
 
-        let apple_music_bearer_token: &str = match &self.apple_music_bearer_token {
-            Some(token) => &token.to_string_lossy(),
-            None => AppleMusic::PUBLIC_BEARER_TOKEN,
-        };
+        if let Some(album_str) = &self.input.album {
+            let mut album = {
+                if self.file {
+                    let album_path = PathBuf::from(album_str);
+                    Album::from_file(&album_path)?
+                } else {
+                    let source_info = get_source_info_from_album_url(album_str)?;
+                    let source = self.source_service(&config, &source_info.service)?;
+                    Album::from_service_id(&client, source.as_ref(), source_info.id).await?
+                }
+            };
+
+            let services = self.conversion_services(
+                &config,
+                cache.as_ref(),
+                &overrides,
+                &album.source_service,
+            )?;
+            for service in &services {
+                album.add_service(&client, service.as_ref()).await?;
+            }
+            self.print_candidates(&client, &services, &album.tracks)
+                .await;
 
-        if let Some(_album_str) = &self.input.album {
-            todo!()
+            if let Some(output_path) = &self.output_file {
+                album.save_to_file(output_path, &album.name)?;
+            } else {
+                println!("{}", serde_json::to_string_pretty(&album)?);
+            }
+
+            if let Some(dir) = &self.download_directory {
+                album.download_tracks(&client, &config, dir, true).await?;
+            }
         } else if let Some(playlist_str) = &self.input.playlist {
             let mut playlist = {
                 if self.file {
                     let playlist_path = PathBuf::from(playlist_str);
                     Playlist::from_file(&playlist_path)?
                 } else {
-                    let source_info = get_source_info_from_playlist_url(&playlist_str)?;
-                    match source_info.service {
-                        Source::Spotify => match &spotify_access_token {
-                            Some(token) => {
-                                Playlist::from_spotify_id(&client, &token, &source_info.id).await?
-                            }
-                            None => {
-                                return Err(Error::DatabaseError(
-                                    "No Spotify Access Token (--ST) Provided".to_string(),
-                                ))
-                            }
-                        },
-                        Source::AppleMusic => {
-                            Playlist::from_apple_music_id(
-                                &client,
-                                apple_music_bearer_token,
-                                &source_info.id,
-                            )
-                            .await?
-                        }
-                    }
+                    let source_info = get_source_info_from_playlist_url(playlist_str)?;
+                    let source = self.source_service(&config, &source_info.service)?;
+                    Playlist::from_service_id(&client, source.as_ref(), source_info.id).await?
                 }
             };
 
-            if self.conversion_outputs.spotify && playlist.source_service != Source::Spotify {
-                match &spotify_access_token {
-                    Some(token) => {
-                        playlist.add_spotify(&client, &token).await?;
-                    }
-                    None => {
-                        return Err(Error::DatabaseError(
-                            "No Spotify Access Token (--ST) Provided".to_string(),
-                        ))
-                    }
-                }
-            }
-
-            if self.conversion_outputs.apple_music && playlist.source_service != Source::AppleMusic
-            {
-                playlist
-                    .add_apple_music(&client, apple_music_bearer_token)
-                    .await?;
-            }
-            if self.conversion_outputs.bandcamp {
-                playlist.add_bandcamp(&client).await?;
-            }
-            if self.conversion_outputs.youtube {
-                playlist.add_youtube(&client).await?;
+            let services = self.conversion_services(
+                &config,
+                cache.as_ref(),
+                &overrides,
+                &playlist.source_service,
+            )?;
+            for service in &services {
+                playlist.add_service(&client, service.as_ref()).await?;
             }
+            self.print_candidates(&client, &services, &playlist.tracks)
+                .await;
 
             if let Some(output_path) = &self.output_file {
                 playlist.save_to_file(output_path, &playlist.name)?;
@@ -166,7 +412,9 @@ impl Cli {
             }
 
             if let Some(dir) = &self.download_directory {
-                playlist.download_tracks(&client, dir, true).await?;
+                playlist
+                    .download_tracks(&client, &config, dir, true)
+                    .await?;
             }
         } else if let Some(track_str) = &self.input.track {
             let mut track = {
@@ -174,84 +422,313 @@ impl Cli {
                     let track_path = PathBuf::from(track_str);
                     Track::from_file(&track_path)?
                 } else {
-                    let source_info = get_source_info_from_track_url(&track_str)?;
-                    match source_info.service {
-                        Source::Spotify => match &spotify_access_token {
-                            Some(token) => {
-                                Track::from_spotify_id(&client, &token, &source_info.id).await?
-                            }
-                            None => {
-                                return Err(Error::DatabaseError(
-                                    "No Spotify Access Token (--ST) Provided".to_string(),
-                                ))
-                            }
-                        },
-                        Source::AppleMusic => {
-                            Track::from_apple_music_id(
-                                &client,
-                                apple_music_bearer_token,
-                                &source_info.id,
-                            )
-                            .await?
-                        }
-                    }
+                    let source_info = get_source_info_from_track_url(track_str)?;
+                    let source = self.source_service(&config, &source_info.service)?;
+                    Track::from_service_id(&client, source.as_ref(), source_info.id).await?
                 }
             };
 
-            if self.conversion_outputs.spotify && track.source_service != Source::Spotify {
-                match &spotify_access_token {
-                    Some(token) => {
-                        match track.add_spotify(&client, &token).await {
-                            Ok(..) => (),
-                            Err(e) => log::warn!("unable to add Spotify: {}", e),
-                        };
-                    }
-                    None => {
-                        return Err(Error::DatabaseError(
-                            "No Spotify Access Token (--ST) Provided".to_string(),
-                        ))
-                    }
-                }
-            }
-
-            if self.conversion_outputs.apple_music && track.source_service != Source::AppleMusic {
-                match track
-                    .add_apple_music(&client, apple_music_bearer_token)
-                    .await
-                {
-                    Ok(..) => (),
-                    Err(e) => log::warn!("unable to add Apple Music: {}", e),
-                };
-            }
-            if self.conversion_outputs.bandcamp {
-                match track.add_bandcamp(&client).await {
-                    Ok(..) => (),
-                    Err(e) => log::warn!("unable to add Bandcamp: {}", e),
-                };
-            }
-            if self.conversion_outputs.youtube {
-                match track.add_youtube(&client).await {
+            let services = self.conversion_services(
+                &config,
+                cache.as_ref(),
+                &overrides,
+                &track.source_service,
+            )?;
+            for service in &services {
+                match track.add_service(&client, service.as_ref()).await {
                     Ok(..) => (),
-                    Err(e) => log::warn!("unable to add YouTube: {}", e),
+                    Err(e) => log::warn!("unable to add {}: {}", service.name(), e),
                 };
             }
+            self.print_candidates(&client, &services, std::slice::from_ref(&track))
+                .await;
 
             if let Some(output_path) = &self.output_file {
-                track.save_to_file(
-                    output_path,
-                    &format!("{} - {}", track.name, track.artists[0]),
-                )?;
+                // YouTube and Bandcamp tracks may come without artists
+                let filename: String = match track.artists.first() {
+                    Some(artist) => format!("{} - {}", track.name, artist),
+                    None => track.name.to_owned(),
+                };
+                track.save_to_file(output_path, &filename)?;
             } else {
                 println!("{}", serde_json::to_string_pretty(&track)?);
             }
 
             if let Some(dir) = &self.download_directory {
-                track.download(&client, dir, &track.name, true).await?;
+                track
+                    .download(&client, &config, dir, &track.name, true)
+                    .await?;
             }
         }
 
         Ok(())
     }
+
+    /// Token from --ST, otherwise Client Credentials or the user login for
+    /// --spotify-client-id
+    fn spotify_credentials(&self, config: &Config) -> Result<Arc<dyn CredentialProvider>> {
+        if let Some(token) = &self.spotify_access_token {
+            return Ok(Arc::new(StaticToken(token.to_owned())));
+        }
+
+        let client_id: &str =
+            match &self.spotify_client_id {
+                Some(client_id) => client_id,
+                None => return Err(Error::InvalidInput(
+                    "No Spotify Access Token (--ST) or Client ID (--spotify-client-id) Provided"
+                        .to_string(),
+                )),
+            };
+
+        match &self.spotify_client_secret {
+            Some(client_secret) => {
+                let mut credentials = SpotifyClientCredentials::with_config(
+                    client_id,
+                    client_secret,
+                    config.spotify.clone(),
+                );
+                if let Some(path) = token_cache_file("spotify_client_credentials", client_id) {
+                    credentials = credentials.with_cache_file(&path);
+                }
+                Ok(Arc::new(credentials))
+            }
+            None => {
+                let mut pkce = SpotifyPkce::with_config(
+                    client_id,
+                    SpotifyPkce::DEFAULT_REDIRECT_URI,
+                    config.spotify.clone(),
+                );
+                if let Some(path) = token_cache_file("spotify_user", client_id) {
+                    pkce = pkce.with_cache_file(&path);
+                }
+                Ok(Arc::new(pkce))
+            }
+        }
+    }
+
+    /// Token from --AT, otherwise one minted from the MusicKit key or, without
+    /// one, scraped from the web player
+    fn apple_music_credentials(&self, config: &Config) -> Result<Arc<dyn CredentialProvider>> {
+        if let Some(token) = &self.apple_music_bearer_token {
+            return Ok(Arc::new(StaticToken(token.to_owned())));
+        }
+
+        if let (Some(team_id), Some(key_id), Some(private_key)) = (
+            &self.apple_team_id,
+            &self.apple_key_id,
+            &self.apple_private_key,
+        ) {
+            return Ok(Arc::new(AppleDeveloperToken::from_file(
+                team_id,
+                key_id,
+                private_key,
+            )?));
+        }
+
+        let mut web_player = AppleMusicWebPlayer::with_config(config.apple_music.clone());
+        if let Some(path) = token_cache_file("apple_music", "web_player") {
+            web_player = web_player.with_cache_file(&path);
+        }
+        Ok(Arc::new(web_player))
+    }
+
+    /// Service used to retrieve the input track/playlist/album
+    fn source_service(&self, config: &Config, source: &Source) -> Result<Box<dyn MusicService>> {
+        match source {
+            Source::Spotify => Ok(Box::new(SpotifyProvider::with_credentials(
+                self.spotify_credentials(config)?,
+                config.spotify.clone(),
+            ))),
+            Source::AppleMusic => Ok(Box::new(AppleMusicProvider::with_credentials(
+                self.apple_music_credentials(config)?,
+                config.apple_music.clone(),
+            ))),
+            Source::YouTube => Ok(Box::new(YouTubeProvider::with_config(
+                config.youtube.clone(),
+            ))),
+            Source::Bandcamp => Ok(Box::new(BandcampProvider::with_config(
+                config.bandcamp.clone(),
+            ))),
+            Source::Deezer => Ok(Box::new(DeezerProvider::with_config(config.deezer.clone()))),
+            Source::Other(name) => Err(Error::InvalidInput(format!(
+                "{} cannot be used as a source",
+                name
+            ))),
+        }
+    }
+
+    /// Print the top --candidates search results of every service for each of
+    /// `tracks` to stderr, keeping stdout for the JSON output
+    async fn print_candidates(
+        &self,
+        client: &Client,
+        services: &[Box<dyn MusicService>],
+        tracks: &[Track],
+    ) {
+        let Some(count) = self.candidates else {
+            return;
+        };
+
+        for track in tracks {
+            for service in services {
+                let reports = match track.search_service(client, service.as_ref()).await {
+                    Ok(reports) => reports,
+                    Err(e) => {
+                        log::warn!(
+                            "\tUnable to search {} for {}: {}",
+                            service.name(),
+                            track.name,
+                            e
+                        );
+                        continue;
+                    }
+                };
+
+                eprintln!(
+                    "{} candidates for {} - {}:",
+                    service.name(),
+                    track.name,
+                    track.artists.join(", ")
+                );
+                for (i, report) in reports.iter().take(count).enumerate() {
+                    eprintln!(
+                        "{:>3}. {:.3}{} {} - {} ({}) {}",
+                        i + 1,
+                        report.score,
+                        if report.is_match() { "*" } else { " " },
+                        report.candidate.name,
+                        report.candidate.artists.join(", "),
+                        report.candidate.album.as_deref().unwrap_or("-"),
+                        report.candidate.url
+                    );
+                }
+            }
+        }
+    }
+
+    /// Match cache to consult, unless disabled with --no-cache or --prefer
+    fn match_cache(&self) -> Option<Arc<MatchCache>> {
+        if self.no_cache {
+            return None;
+        }
+        // cached matches were made for the version of the input track
+        if self.prefer != Prefer::Same {
+            log::info!("Not using the match cache with --prefer {:?}", self.prefer);
+            return None;
+        }
+
+        match MatchCache::default_path() {
+            Some(path) => Some(Arc::new(MatchCache::open(&path))),
+            None => {
+                log::warn!("Unable to find cache directory, not caching matches");
+                None
+            }
+        }
+    }
+
+    /// Region from --region, otherwise the one in the input URL
+    fn region(&self) -> Option<&str> {
+        if let Some(region) = &self.region {
+            return Some(region);
+        }
+        if self.file {
+            return None;
+        }
+
+        let url: &str = self
+            .input
+            .album
+            .as_ref()
+            .or(self.input.playlist.as_ref())
+            .or(self.input.track.as_ref())?;
+        get_region_from_url(url)
+    }
+
+    /// Overrides file from --overrides, otherwise the default one
+    fn overrides_path(&self) -> Result<PathBuf> {
+        match &self.overrides {
+            Some(path) => Ok(path.to_owned()),
+            None => Overrides::default_path().ok_or(Error::DatabaseError(
+                "Unable to find config directory".to_string(),
+            )),
+        }
+    }
+
+    /// Overrides used while converting. A file passed with --overrides has to
+    /// load, but problems with the default one only log a warning.
+    fn match_overrides(&self) -> Result<Overrides> {
+        match &self.overrides {
+            Some(path) => Overrides::open(path),
+            None => Ok(Overrides::open_default()),
+        }
+    }
+
+    /// Services requested on the command line, excluding the one the input came from
+    fn conversion_services(
+        &self,
+        config: &Config,
+        cache: Option<&Arc<MatchCache>>,
+        overrides: &Arc<Overrides>,
+        source: &Source,
+    ) -> Result<Vec<Box<dyn MusicService>>> {
+        let mut services: Vec<Box<dyn MusicService>> = Vec::new();
+
+        if self.conversion_outputs.spotify && *source != Source::Spotify {
+            services.push(Box::new(SpotifyProvider::with_credentials(
+                self.spotify_credentials(config)?,
+                config.spotify.clone(),
+            )));
+        }
+        if self.conversion_outputs.apple_music && *source != Source::AppleMusic {
+            services.push(Box::new(AppleMusicProvider::with_credentials(
+                self.apple_music_credentials(config)?,
+                config.apple_music.clone(),
+            )));
+        }
+        if self.conversion_outputs.bandcamp && *source != Source::Bandcamp {
+            services.push(Box::new(BandcampProvider::with_config(
+                config.bandcamp.clone(),
+            )));
+        }
+        if self.conversion_outputs.youtube && *source != Source::YouTube {
+            services.push(Box::new(YouTubeProvider::with_config(
+                config.youtube.clone(),
+            )));
+        }
+        if self.conversion_outputs.deezer && *source != Source::Deezer {
+            services.push(Box::new(DeezerProvider::with_config(config.deezer.clone())));
+        }
+
+        Ok(services
+            .into_iter()
+            .map(|service| {
+                Box::new(with_overrides_and_cache(
+                    service,
+                    overrides.clone(),
+                    cache.cloned(),
+                )) as Box<dyn MusicService>
+            })
+            .collect())
+    }
+}
+
+/// Exit code for `error`, see `Cli`'s help text
+pub fn exit_code(error: &Error) -> u8 {
+    match error {
+        Error::InvalidInput(..) => 2,
+        Error::NotFound { .. } => 3,
+        Error::RateLimited { .. } => 4,
+        Error::Unauthorized(..) => 5,
+        Error::UnexpectedResponse { .. } => 6,
+        Error::ExternalToolMissing(..) => 7,
+        Error::RetrievalError(..) => 8,
+        _ => 1,
+    }
+}
+
+/// File in the cache directory holding the `kind` token for `id`
+fn token_cache_file(kind: &str, id: &str) -> Option<PathBuf> {
+    Some(cache_dir()?.join(format!("{}_{}.json", kind, id)))
 }
 
 struct SourceInfo<'a> {
@@ -259,7 +736,7 @@ struct SourceInfo<'a> {
     service: Source,
 }
 
-fn get_source_info_from_playlist_url(url: &str) -> Result<SourceInfo> {
+fn get_source_info_from_playlist_url(url: &str) -> Result<SourceInfo<'_>> {
     let apple_music_re =
         regex::Regex::new(r#"(?:https://)?music\.apple\.com/\S\S/playlist/\S+/(pl\.\S{32})"#)?;
 
@@ -283,12 +760,38 @@ fn get_source_info_from_playlist_url(url: &str) -> Result<SourceInfo> {
         }
     }
 
-    Err(Error::TrackError(
-        "Not valid input playlist URL".to_string(),
-    ))
+    let youtube_re = regex::Regex::new(
+        r#"(?:https://)?(?:www\.|music\.|m\.)?youtube\.com/\S*[?&]list=([\w-]+)"#,
+    )?;
+
+    if let Some(captures) = youtube_re.captures(url) {
+        if let Some(m) = captures.get(1) {
+            return Ok(SourceInfo {
+                id: m.as_str(),
+                service: Source::YouTube,
+            });
+        }
+    }
+
+    let deezer_re =
+        regex::Regex::new(r#"(?:https://)?(?:www\.)?deezer\.com/(?:\w\w/)?playlist/(\d+)"#)?;
+
+    if let Some(captures) = deezer_re.captures(url) {
+        if let Some(m) = captures.get(1) {
+            return Ok(SourceInfo {
+                id: m.as_str(),
+                service: Source::Deezer,
+            });
+        }
+    }
+
+    Err(Error::InvalidInput(format!(
+        "Not valid input playlist URL: {}",
+        url
+    )))
 }
 
-fn get_source_info_from_album_url(url: &str) -> Result<SourceInfo> {
+fn get_source_info_from_album_url(url: &str) -> Result<SourceInfo<'_>> {
     let apple_music_re =
         regex::Regex::new(r#"(?:https://)?music\.apple\.com/\S\S/album/\S+/(\d{10})"#)?;
 
@@ -312,9 +815,46 @@ fn get_source_info_from_album_url(url: &str) -> Result<SourceInfo> {
         }
     }
 
-    Err(Error::TrackError("Not valid input album URL".to_string()))
+    let youtube_re =
+        regex::Regex::new(r#"(?:https://)?music\.youtube\.com/browse/(MPREb_[\w-]+)"#)?;
+
+    if let Some(captures) = youtube_re.captures(url) {
+        if let Some(m) = captures.get(1) {
+            return Ok(SourceInfo {
+                id: m.as_str(),
+                service: Source::YouTube,
+            });
+        }
+    }
+
+    let deezer_re =
+        regex::Regex::new(r#"(?:https://)?(?:www\.)?deezer\.com/(?:\w\w/)?album/(\d+)"#)?;
+
+    if let Some(captures) = deezer_re.captures(url) {
+        if let Some(m) = captures.get(1) {
+            return Ok(SourceInfo {
+                id: m.as_str(),
+                service: Source::Deezer,
+            });
+        }
+    }
+
+    // Bandcamp albums are looked up by their URL
+    let bandcamp_re = regex::Regex::new(r#"^(?:https?://)?[\w-]+\.bandcamp\.com/album/[\w-]+"#)?;
+
+    if let Some(m) = bandcamp_re.find(url) {
+        return Ok(SourceInfo {
+            id: m.as_str(),
+            service: Source::Bandcamp,
+        });
+    }
+
+    Err(Error::InvalidInput(format!(
+        "Not valid input album URL: {}",
+        url
+    )))
 }
-fn get_source_info_from_track_url(url: &str) -> Result<SourceInfo> {
+fn get_source_info_from_track_url(url: &str) -> Result<SourceInfo<'_>> {
     let apple_music_re =
         regex::Regex::new(r#"(?:https://)?music\.apple\.com/\S\S/album/\S+/\d{10}\?i=(\d{10})"#)?;
 
@@ -338,5 +878,92 @@ fn get_source_info_from_track_url(url: &str) -> Result<SourceInfo> {
         }
     }
 
-    Err(Error::TrackError("Not valid input track URL".to_string()))
+    let youtube_re = regex::Regex::new(
+        r#"(?:https://)?(?:(?:www\.|music\.|m\.)?youtube\.com/watch\?(?:\S*&)?v=|youtu\.be/)([\w-]{11})"#,
+    )?;
+
+    if let Some(captures) = youtube_re.captures(url) {
+        if let Some(m) = captures.get(1) {
+            return Ok(SourceInfo {
+                id: m.as_str(),
+                service: Source::YouTube,
+            });
+        }
+    }
+
+    let deezer_re =
+        regex::Regex::new(r#"(?:https://)?(?:www\.)?deezer\.com/(?:\w\w/)?track/(\d+)"#)?;
+
+    if let Some(captures) = deezer_re.captures(url) {
+        if let Some(m) = captures.get(1) {
+            return Ok(SourceInfo {
+                id: m.as_str(),
+                service: Source::Deezer,
+            });
+        }
+    }
+
+    // Bandcamp tracks are looked up by their URL
+    let bandcamp_re = regex::Regex::new(r#"^(?:https?://)?[\w-]+\.bandcamp\.com/track/[\w-]+"#)?;
+
+    if let Some(m) = bandcamp_re.find(url) {
+        return Ok(SourceInfo {
+            id: m.as_str(),
+            service: Source::Bandcamp,
+        });
+    }
+
+    Err(Error::InvalidInput(format!(
+        "Not valid input track URL: {}",
+        url
+    )))
+}
+
+/// Storefront in an Apple Music URL, e.g. `gb` in `music.apple.com/gb/album/...`
+fn get_region_from_url(url: &str) -> Option<&str> {
+    let apple_music_re = regex::Regex::new(r#"(?:https://)?music\.apple\.com/(\w\w)/"#).ok()?;
+
+    Some(apple_music_re.captures(url)?.get(1)?.as_str())
+}
+
+struct TargetInfo<'a> {
+    id: &'a str,
+    /// `MusicService::name` of the service
+    service: String,
+}
+
+/// `MusicService::name` of the service a URL was parsed for
+fn source_name(source: &Source) -> &str {
+    match source {
+        Source::Spotify => "Spotify",
+        Source::AppleMusic => "Apple Music",
+        Source::YouTube => "YouTube",
+        Source::Bandcamp => "Bandcamp",
+        Source::Deezer => "Deezer",
+        Source::Other(name) => name,
+    }
+}
+
+/// Service and id of a track URL to force as a match. Bandcamp tracks are
+/// looked up by their URL rather than an id.
+fn get_target_info_from_track_url(url: &str) -> Result<TargetInfo<'_>> {
+    let source_info = get_source_info_from_track_url(url)
+        .map_err(|_| Error::InvalidInput(format!("Not valid target track URL: {}", url)))?;
+    Ok(TargetInfo {
+        id: source_info.id,
+        service: source_name(&source_info.service).to_owned(),
+    })
+}
+
+/// Overrides key of a track URL, or of an `isrc:<ISRC>`
+fn get_override_key(source: &str) -> Result<String> {
+    if let Some(isrc) = source.strip_prefix("isrc:") {
+        return Ok(Overrides::isrc_key(isrc));
+    }
+
+    let source_info = get_source_info_from_track_url(source)?;
+    Ok(Overrides::id_key(
+        source_name(&source_info.service),
+        source_info.id,
+    ))
 }
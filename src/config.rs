@@ -0,0 +1,320 @@
+use crate::apple_music::AppleMusic;
+use crate::bandcamp::Bandcamp;
+use crate::deezer::Deezer;
+use crate::error::{Error, Result};
+use crate::matcher::{ExplicitPreference, Matcher};
+use crate::request::{RequestExecutor, RequestPolicy};
+use crate::spotify::Spotify;
+use crate::youtube::YouTube;
+use reqwest::header::{HeaderMap, USER_AGENT};
+use reqwest::{Client, Method, RequestBuilder, Response};
+use std::future::Future;
+
+/// Where and how a single service is reached.
+///
+/// Every request a service makes goes through `ServiceConfig::request`, so
+/// pointing `api_base_url`/`site_base_url` (and `media_base_url`) at a local
+/// server is enough to run the whole conversion pipeline against a stand-in.
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    /// Base of every API path, e.g. `https://api.spotify.com/v1`
+    pub api_base_url: String,
+    /// Public website of the service, used for scraping and `Origin` headers
+    pub site_base_url: String,
+    /// Where tokens are requested, e.g. `https://accounts.spotify.com`.
+    /// Defaults to `site_base_url`.
+    pub auth_base_url: String,
+    /// When set, absolute media URLs returned by the service (artwork, audio
+    /// streams) are fetched from this base instead, with their host and path
+    /// appended so every host can still be told apart
+    pub media_base_url: Option<String>,
+    /// Country code of the catalog to use, e.g. `us`
+    pub storefront: String,
+    /// Storefronts searched, in order, for tracks `storefront` doesn't have
+    pub fallback_storefronts: Vec<String>,
+    /// Language code for localized responses, e.g. `en`
+    pub language: String,
+    /// Extra headers added to every request
+    pub headers: HeaderMap,
+    /// Overrides the `reqwest::Client` user agent for this service
+    pub user_agent: Option<String>,
+    /// Paces and retries every request, shared by all clones of this config
+    pub executor: RequestExecutor,
+    /// Decides which search results count as the same track
+    pub matcher: Matcher,
+}
+
+/// `ServiceConfig` for every built-in service
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub spotify: ServiceConfig,
+    pub apple_music: ServiceConfig,
+    pub youtube: ServiceConfig,
+    pub bandcamp: ServiceConfig,
+    pub deezer: ServiceConfig,
+}
+
+impl ServiceConfig {
+    pub fn new(api_base_url: &str, site_base_url: &str) -> Self {
+        ServiceConfig {
+            api_base_url: api_base_url.to_owned(),
+            site_base_url: site_base_url.to_owned(),
+            auth_base_url: site_base_url.to_owned(),
+            media_base_url: None,
+            storefront: "us".to_string(),
+            fallback_storefronts: Vec::new(),
+            language: "en".to_string(),
+            headers: HeaderMap::new(),
+            user_agent: None,
+            executor: RequestExecutor::default(),
+            matcher: Matcher::default(),
+        }
+    }
+
+    pub fn spotify() -> Self {
+        Self {
+            auth_base_url: Spotify::ACCOUNTS_BASE_URL.to_owned(),
+            ..Self::new(Spotify::API_BASE_URL, Spotify::SITE_BASE_URL)
+        }
+    }
+
+    pub fn apple_music() -> Self {
+        Self::new(AppleMusic::API_BASE_URL, AppleMusic::SITE_BASE_URL)
+    }
+
+    pub fn youtube() -> Self {
+        Self {
+            matcher: Matcher::new(YouTube::MATCH_THRESHOLD),
+            ..Self::new(YouTube::API_BASE_URL, YouTube::SITE_BASE_URL)
+        }
+    }
+
+    pub fn bandcamp() -> Self {
+        Self::new(Bandcamp::API_BASE_URL, Bandcamp::SITE_BASE_URL)
+    }
+
+    pub fn deezer() -> Self {
+        Self::new(Deezer::API_BASE_URL, Deezer::SITE_BASE_URL)
+    }
+
+    /// Serve the API, website, token endpoint and media from `base_url`
+    /// (e.g. `http://127.0.0.1:8080`), keeping the paths of the default URLs
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.api_base_url = rebase_url(&self.api_base_url, base_url);
+        self.site_base_url = rebase_url(&self.site_base_url, base_url);
+        self.auth_base_url = rebase_url(&self.auth_base_url, base_url);
+        self.media_base_url = Some(base_url.trim_end_matches('/').to_owned());
+        self
+    }
+
+    /// Replace the request budget and retry behaviour of this service
+    pub fn with_request_policy(mut self, policy: RequestPolicy) -> Self {
+        self.executor = RequestExecutor::new(policy);
+        self
+    }
+
+    /// Use the catalog of the country `storefront`, e.g. `gb`
+    pub fn with_storefront(mut self, storefront: &str) -> Self {
+        self.storefront = storefront.to_lowercase();
+        self
+    }
+
+    /// Search `storefronts`, in order, for tracks `storefront` doesn't have
+    pub fn with_fallback_storefronts(mut self, storefronts: &[String]) -> Self {
+        self.fallback_storefronts = storefronts
+            .iter()
+            .map(|storefront| storefront.to_lowercase())
+            .filter(|storefront| *storefront != self.storefront)
+            .collect();
+        self
+    }
+
+    /// This config switched to each of `fallback_storefronts`
+    pub fn fallback_configs(&self) -> Vec<ServiceConfig> {
+        self.fallback_storefronts
+            .iter()
+            .map(|storefront| ServiceConfig {
+                fallback_storefronts: Vec::new(),
+                ..self.clone().with_storefront(storefront)
+            })
+            .collect()
+    }
+
+    /// Run `find` in `storefront`, then in each of `fallback_storefronts` for as
+    /// long as it returns `Error::NotFound`
+    pub async fn find_in_storefronts<T, F, Fut>(&self, find: F) -> Result<T>
+    where
+        F: Fn(ServiceConfig) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut result: Result<T> = find(self.clone()).await;
+        for config in self.fallback_configs() {
+            if !matches!(result, Err(Error::NotFound { .. })) {
+                break;
+            }
+            log::info!("Not found, trying storefront {}", config.storefront);
+            result = find(config).await;
+        }
+        result
+    }
+
+    /// Replace how search results are scored and accepted for this service
+    pub fn with_matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = matcher;
+        self
+    }
+
+    /// Send a request built with `request`/`request_url` through `executor`
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        self.executor.send(request).await
+    }
+
+    /// Build a request to `path` relative to `api_base_url`
+    pub fn request(&self, client: &Client, method: Method, path: &str) -> RequestBuilder {
+        self.request_url(
+            client,
+            method,
+            &format!("{}/{}", self.api_base_url, path.trim_start_matches('/')),
+        )
+    }
+
+    /// Build a request to an absolute `url`, adding this service's headers
+    pub fn request_url(&self, client: &Client, method: Method, url: &str) -> RequestBuilder {
+        let mut request: RequestBuilder = client.request(method, url).headers(self.headers.clone());
+        if let Some(user_agent) = &self.user_agent {
+            request = request.header(USER_AGENT, user_agent);
+        }
+        request
+    }
+
+    /// Turn a link returned by the API (e.g. a `next` page) into a path usable with
+    /// `request`. Only the path is kept, so links to the public API host still
+    /// resolve against `api_base_url`.
+    pub fn api_path<'a>(&self, url: &'a str) -> &'a str {
+        url_path(url)
+            .trim_start_matches(url_path(&self.api_base_url))
+            .trim_start_matches('/')
+    }
+
+    /// Rewrite an absolute media URL onto `media_base_url`, if one is set,
+    /// e.g. `https://i.scdn.co/image/1` to `{media_base_url}/i.scdn.co/image/1`
+    pub fn media_url(&self, url: &str) -> String {
+        match &self.media_base_url {
+            Some(media_base_url) => format!(
+                "{}/{}",
+                media_base_url,
+                url.split_once("://").map_or(url, |(_, rest)| rest)
+            ),
+            None => url.to_owned(),
+        }
+    }
+}
+
+/// Swap the scheme and host of `url` for `base_url`, keeping the path
+pub(crate) fn rebase_url(url: &str, base_url: &str) -> String {
+    format!("{}{}", base_url.trim_end_matches('/'), url_path(url))
+}
+
+/// Path (and query) of `url`, which may already be root-relative
+fn url_path(url: &str) -> &str {
+    match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |i| &rest[i..]),
+        None => url,
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            spotify: ServiceConfig::spotify(),
+            apple_music: ServiceConfig::apple_music(),
+            youtube: ServiceConfig::youtube(),
+            bandcamp: ServiceConfig::bandcamp(),
+            deezer: ServiceConfig::deezer(),
+        }
+    }
+}
+
+impl Config {
+    /// Use `policy` for every service
+    pub fn with_request_policy(self, policy: RequestPolicy) -> Self {
+        Config {
+            spotify: self.spotify.with_request_policy(policy.clone()),
+            apple_music: self.apple_music.with_request_policy(policy.clone()),
+            youtube: self.youtube.with_request_policy(policy.clone()),
+            bandcamp: self.bandcamp.with_request_policy(policy.clone()),
+            deezer: self.deezer.with_request_policy(policy),
+        }
+    }
+
+    /// Use the catalog of the country `storefront` on every service
+    pub fn with_storefront(self, storefront: &str) -> Self {
+        Config {
+            spotify: self.spotify.with_storefront(storefront),
+            apple_music: self.apple_music.with_storefront(storefront),
+            youtube: self.youtube.with_storefront(storefront),
+            bandcamp: self.bandcamp.with_storefront(storefront),
+            deezer: self.deezer.with_storefront(storefront),
+        }
+    }
+
+    /// Search `storefronts` on every service for tracks the storefront doesn't have
+    pub fn with_fallback_storefronts(self, storefronts: &[String]) -> Self {
+        Config {
+            spotify: self.spotify.with_fallback_storefronts(storefronts),
+            apple_music: self.apple_music.with_fallback_storefronts(storefronts),
+            youtube: self.youtube.with_fallback_storefronts(storefronts),
+            bandcamp: self.bandcamp.with_fallback_storefronts(storefronts),
+            deezer: self.deezer.with_fallback_storefronts(storefronts),
+        }
+    }
+
+    /// Look for the `preference` version of tracks on every service
+    pub fn with_explicit_preference(self, preference: ExplicitPreference) -> Self {
+        let with_preference = |config: ServiceConfig| {
+            let matcher: Matcher = config.matcher.clone().with_explicit_preference(preference);
+            config.with_matcher(matcher)
+        };
+        Config {
+            spotify: with_preference(self.spotify),
+            apple_music: with_preference(self.apple_music),
+            youtube: with_preference(self.youtube),
+            bandcamp: with_preference(self.bandcamp),
+            deezer: with_preference(self.deezer),
+        }
+    }
+
+    /// Point every service at `base_url`, see `ServiceConfig::with_base_url`
+    pub fn with_base_url(self, base_url: &str) -> Self {
+        Config {
+            spotify: self.spotify.with_base_url(base_url),
+            apple_music: self.apple_music.with_base_url(base_url),
+            youtube: self.youtube.with_base_url(base_url),
+            bandcamp: self.bandcamp.with_base_url(base_url),
+            deezer: self.deezer.with_base_url(base_url),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::config::ServiceConfig;
+
+    #[test]
+    fn with_base_url_keeps_paths() {
+        let config: ServiceConfig =
+            ServiceConfig::spotify().with_base_url("http://127.0.0.1:8080/");
+
+        assert_eq!(config.api_base_url, "http://127.0.0.1:8080/v1");
+        assert_eq!(config.site_base_url, "http://127.0.0.1:8080");
+        assert_eq!(
+            config.media_url("https://i.scdn.co/image/ab67616d0000b273"),
+            "http://127.0.0.1:8080/i.scdn.co/image/ab67616d0000b273"
+        );
+        assert_eq!(
+            config.api_path("https://api.spotify.com/v1/albums/0sNxQhDTG4vCahzPTjCGKg/tracks"),
+            "albums/0sNxQhDTG4vCahzPTjCGKg/tracks"
+        );
+    }
+}
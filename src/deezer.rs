@@ -0,0 +1,770 @@
+use crate::album;
+use crate::config::ServiceConfig;
+use crate::error::{Error, Result};
+use crate::matcher::{Candidate, MatchReport, Matcher};
+use crate::normalize::{search_text, search_title};
+use crate::playlist::Playlist;
+use crate::service::{Album, Artist, MatchMethod, MatchProvenance, MusicService, Services, Source};
+use crate::track::Track;
+use crate::utils::{parse_release_date, parse_response};
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::{Client, Method, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct Deezer {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub artists: Vec<Artist>,
+    pub album: Album,
+    pub duration_ms: usize,
+    pub image: Option<String>,
+    pub audio_preview: Option<String>,
+    /// How this record was matched to its track
+    pub provenance: Option<MatchProvenance>,
+}
+
+/// `MusicService` implementation for the public Deezer API, which needs no key
+pub struct DeezerProvider {
+    pub config: ServiceConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawTrack {
+    id: u64,
+    title: String,
+    link: String,
+    /// Seconds
+    duration: usize,
+    explicit_lyrics: bool,
+    preview: Option<String>,
+    artist: RawArtist,
+    album: RawTrackAlbum,
+    // only in full track objects, not in search results
+    isrc: Option<String>,
+    track_position: Option<usize>,
+    disk_number: Option<usize>,
+    release_date: Option<String>,
+    contributors: Option<Vec<RawArtist>>,
+}
+
+/// Track in the track list of an album or playlist
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawTrackItem {
+    id: u64,
+    title: String,
+    duration: usize,
+    explicit_lyrics: bool,
+    artist: RawArtist,
+    r#type: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawTrackAlbum {
+    id: u64,
+    title: String,
+    link: Option<String>,
+    cover_xl: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawArtist {
+    id: u64,
+    name: String,
+    link: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawAlbum {
+    id: u64,
+    title: String,
+    upc: Option<String>,
+    link: String,
+    label: Option<String>,
+    nb_tracks: usize,
+    release_date: String,
+    artist: RawArtist,
+    contributors: Option<Vec<RawArtist>>,
+    tracks: RawPage<RawTrackItem>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawPlaylist {
+    id: u64,
+    title: String,
+    description: Option<String>,
+    picture_xl: Option<String>,
+    creator: Option<RawCreator>,
+    tracks: RawPage<RawTrackItem>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawCreator {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawPage<T> {
+    data: Vec<T>,
+    next: Option<String>,
+}
+
+impl Deezer {
+    pub const API_BASE_URL: &'static str = "https://api.deezer.com";
+    pub const SITE_BASE_URL: &'static str = "https://www.deezer.com";
+
+    /// Error code of a lookup that found nothing
+    const NO_DATA_ERROR: u64 = 800;
+    /// Error code of a request over the quota
+    const QUOTA_ERROR: u64 = 4;
+
+    /// Errors are returned as `{"error": {...}}` with a 200 status
+    async fn get(client: &Client, config: &ServiceConfig, path: &str) -> Result<Value> {
+        Self::get_with_query(client, config, path, &[]).await
+    }
+
+    /// `get` with the query string `query`, encoded by reqwest
+    async fn get_with_query(
+        client: &Client,
+        config: &ServiceConfig,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Value> {
+        let response: Response = config
+            .send(config.request(client, Method::GET, path).query(query))
+            .await?;
+
+        let body: String = response.text().await?;
+        let data: Value = serde_json::from_str(&body)
+            .map_err(|_| Error::unexpected_response("Deezer", path, &body))?;
+
+        match data["error"]["code"].as_u64() {
+            None => Ok(data),
+            Some(Self::NO_DATA_ERROR) => Err(Error::NotFound {
+                service: "Deezer".to_string(),
+                query: path.to_owned(),
+            }),
+            Some(Self::QUOTA_ERROR) => Err(Error::RateLimited { retry_after: None }),
+            Some(_) => Err(Error::unexpected_response("Deezer", path, &body)),
+        }
+    }
+
+    async fn get_raw_track(
+        client: &Client,
+        config: &ServiceConfig,
+        path: &str,
+    ) -> Result<RawTrack> {
+        parse_response("Deezer", path, &Self::get(client, config, path).await?)
+    }
+
+    fn candidate_from_raw(raw_track: &RawTrack) -> Candidate {
+        Candidate {
+            id: raw_track.id.to_string(),
+            url: raw_track.link.to_owned(),
+            name: raw_track.title.to_owned(),
+            artists: artist_names(&raw_track.artist, raw_track.contributors.as_deref()),
+            album: Some(raw_track.album.title.to_owned()),
+            duration_ms: Some(raw_track.duration * 1000),
+            isrc: raw_track.isrc.to_owned(),
+            is_explicit: Some(raw_track.explicit_lyrics),
+            release_year: raw_track
+                .release_date
+                .as_deref()
+                .and_then(|release_date| parse_release_date(release_date).ok())
+                .map(|(year, _, _)| year),
+        }
+    }
+
+    fn candidate_from_raw_item(raw_album: &RawAlbum, raw_track_item: &RawTrackItem) -> Candidate {
+        Candidate {
+            id: raw_track_item.id.to_string(),
+            name: raw_track_item.title.to_owned(),
+            artists: vec![raw_track_item.artist.name.to_owned()],
+            album: Some(raw_album.title.to_owned()),
+            duration_ms: Some(raw_track_item.duration * 1000),
+            is_explicit: Some(raw_track_item.explicit_lyrics),
+            release_year: parse_release_date(&raw_album.release_date)
+                .ok()
+                .map(|(year, _, _)| year),
+            ..Default::default()
+        }
+    }
+
+    /// Query searching for `track`'s metadata. Only the primary artist is
+    /// searched for, since `artist:` has to match a single Deezer artist.
+    fn search_query(track: &Track) -> Result<String> {
+        Ok(format!(
+            "track:\"{}\" artist:\"{}\" album:\"{}\"",
+            search_title(&track.name),
+            search_text(track.artists.first().ok_or(Error::TrackError(
+                "Track requires at least one artist".to_string()
+            ))?),
+            search_title(&track.album),
+        ))
+    }
+
+    /// Tracks found by searching for `query`, ranked against `track`
+    async fn get_ranked_raw_tracks(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &Track,
+        query: &str,
+    ) -> Result<Vec<(MatchReport, RawTrack)>> {
+        let mut raw_result: Value =
+            Self::get_with_query(client, config, "search/track", &[("q", query)]).await?;
+
+        let raw_tracks: Vec<RawTrack> = match raw_result["data"].take() {
+            Value::Array(items) => items
+                .into_iter()
+                .filter_map(|item| serde_json::from_value(item).ok())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(config.matcher.rank(
+            track,
+            raw_tracks
+                .into_iter()
+                .map(|raw_track| (Self::candidate_from_raw(&raw_track), raw_track))
+                .collect(),
+        ))
+    }
+
+    /// UPC of the album `track` is on, from its other service records
+    fn album_upc(track: &Track) -> Option<&str> {
+        let services: &Services = &track.services;
+        [
+            services.apple_music.as_ref().map(|s| &s.album),
+            services.spotify.as_ref().map(|s| &s.album),
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(|album| album.upc.as_deref())
+    }
+
+    /// `track` on the album with its UPC, picked from the album's track list
+    async fn get_raw_track_match_from_upc(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &Track,
+        upc: &str,
+    ) -> Result<(RawTrack, MatchProvenance)> {
+        let query: String = format!("album/upc:{}", upc);
+        let raw_album: RawAlbum = serde_json::from_value(Self::get(client, config, &query).await?)?;
+        let (report, raw_track_item): (MatchReport, &RawTrackItem) = Matcher::best_match(
+            config.matcher.rank(
+                track,
+                raw_album
+                    .tracks
+                    .data
+                    .iter()
+                    .map(|raw_track_item| {
+                        (
+                            Self::candidate_from_raw_item(&raw_album, raw_track_item),
+                            raw_track_item,
+                        )
+                    })
+                    .collect(),
+            ),
+        )
+        .ok_or(Error::NotFound {
+            service: "Deezer".to_string(),
+            query: query.to_owned(),
+        })?;
+
+        let raw_track: RawTrack =
+            Self::get_raw_track(client, config, &format!("track/{}", raw_track_item.id)).await?;
+        Ok((
+            raw_track,
+            MatchProvenance::from_report(MatchMethod::Upc, &report, &query),
+        ))
+    }
+
+    async fn get_raw_track_match_from_track(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &Track,
+    ) -> Result<(RawTrack, MatchProvenance)> {
+        if let Some(isrc) = config.matcher.wanted_isrc(track) {
+            let query: String = format!("track/isrc:{}", isrc);
+            if let Ok(raw_track) = Self::get_raw_track(client, config, &query).await {
+                let report: MatchReport = config
+                    .matcher
+                    .score(track, &Self::candidate_from_raw(&raw_track));
+                let provenance = MatchProvenance::from_report(MatchMethod::Isrc, &report, &query);
+                return Ok((raw_track, provenance));
+            }
+        }
+
+        if let Some(upc) = Self::album_upc(track) {
+            if let Ok(found) = Self::get_raw_track_match_from_upc(client, config, track, upc).await
+            {
+                return Ok(found);
+            }
+        }
+        // no isrc or upc, or looking them up failed
+
+        let query: String = Self::search_query(track)?;
+        let (report, raw_track): (MatchReport, RawTrack) =
+            Matcher::best_match(Self::get_ranked_raw_tracks(client, config, track, &query).await?)
+                .ok_or(Error::NotFound {
+                    service: "Deezer".to_string(),
+                    query: format!("{} - {}", track.name, track.artists.join(", ")),
+                })?;
+
+        // search results are missing ISRCs and track numbers
+        let raw_track: RawTrack =
+            Self::get_raw_track(client, config, &format!("track/{}", raw_track.id)).await?;
+        Ok((
+            raw_track,
+            MatchProvenance::from_report(MatchMethod::FuzzySearch, &report, &query),
+        ))
+    }
+
+    /// Every track found by searching for `track`'s metadata, best match first
+    pub async fn search(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &Track,
+    ) -> Result<Vec<MatchReport>> {
+        Ok(
+            Self::get_ranked_raw_tracks(client, config, track, &Self::search_query(track)?)
+                .await?
+                .into_iter()
+                .map(|(report, _)| report)
+                .collect(),
+        )
+    }
+
+    pub async fn create_service_for_track(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &mut Track,
+    ) -> Result<()> {
+        let (raw_track, provenance): (RawTrack, MatchProvenance) =
+            Self::get_raw_track_match_from_track(client, config, track).await?;
+        track.services.deezer = Some(Self::create_service_from_raw(&raw_track, provenance));
+        Ok(())
+    }
+
+    pub async fn create_track_from_id(
+        client: &Client,
+        config: &ServiceConfig,
+        track_id: &str,
+    ) -> Result<Track> {
+        let path: String = format!("track/{}", track_id);
+        let raw_track: RawTrack =
+            parse_response("Deezer", &path, &Self::get(client, config, &path).await?)?;
+        Self::create_track_from_raw(&raw_track)
+    }
+
+    /// Fetch the full tracks of `track_ids`, which have the ISRCs and track
+    /// numbers that album and playlist track lists are missing. Tracks that
+    /// can't be fetched are skipped.
+    async fn create_tracks_from_ids(
+        client: &Client,
+        config: &ServiceConfig,
+        track_ids: &[u64],
+    ) -> Vec<Track> {
+        let track_results: Vec<Result<Track>> = futures::stream::iter(track_ids.iter().copied())
+            .map(|track_id| async move {
+                Self::create_track_from_id(client, config, &track_id.to_string()).await
+            })
+            .buffered(config.executor.policy().max_concurrent_requests)
+            .collect()
+            .await;
+
+        let mut tracks: Vec<Track> = Vec::with_capacity(track_ids.len());
+        for (track_result, track_id) in track_results.into_iter().zip(track_ids) {
+            match track_result {
+                Ok(track) => tracks.push(track),
+                Err(e) => log::warn!("\tSkipping track {}: {}", track_id, e),
+            }
+        }
+        tracks
+    }
+
+    /// Append every page after the first to `raw_page`
+    async fn get_remaining_raw_track_items(
+        client: &Client,
+        config: &ServiceConfig,
+        raw_page: &mut RawPage<RawTrackItem>,
+    ) -> Result<()> {
+        let mut next: Option<String> = raw_page.next.take();
+        while let Some(next_url) = next {
+            let mut page: RawPage<RawTrackItem> = serde_json::from_value(
+                Self::get(client, config, config.api_path(&next_url)).await?,
+            )?;
+            if page.data.is_empty() {
+                break;
+            }
+            raw_page.data.append(&mut page.data);
+            next = page.next;
+        }
+        Ok(())
+    }
+
+    pub async fn create_album_from_id(
+        client: &Client,
+        config: &ServiceConfig,
+        album_id: &str,
+    ) -> Result<album::Album> {
+        let path: String = format!("album/{}", album_id);
+        let mut raw_album: RawAlbum =
+            parse_response("Deezer", &path, &Self::get(client, config, &path).await?)?;
+        Self::get_remaining_raw_track_items(client, config, &mut raw_album.tracks).await?;
+
+        let track_ids: Vec<u64> = raw_album.tracks.data.iter().map(|item| item.id).collect();
+        let mut tracks: Vec<Track> = Self::create_tracks_from_ids(client, config, &track_ids).await;
+        tracks.sort_by_key(|track| (track.disk_number, track.track_number));
+
+        let (release_year, release_month, release_day) =
+            parse_release_date(&raw_album.release_date)?;
+
+        Ok(album::Album {
+            name: raw_album.title.to_owned(),
+            tracks,
+            id: raw_album.id.to_string(),
+            source_service: Source::Deezer,
+            release_year,
+            release_month,
+            release_day,
+            artists: artist_names(&raw_album.artist, raw_album.contributors.as_deref()),
+            upc: raw_album.upc.to_owned(),
+            label: raw_album.label.to_owned(),
+        })
+    }
+
+    pub async fn create_playlist_from_id(
+        client: &Client,
+        config: &ServiceConfig,
+        playlist_id: &str,
+    ) -> Result<Playlist> {
+        let path: String = format!("playlist/{}", playlist_id);
+        let mut raw_playlist: RawPlaylist =
+            parse_response("Deezer", &path, &Self::get(client, config, &path).await?)?;
+        Self::get_remaining_raw_track_items(client, config, &mut raw_playlist.tracks).await?;
+
+        let mut track_ids: Vec<u64> = Vec::with_capacity(raw_playlist.tracks.data.len());
+        for (i, item) in raw_playlist.tracks.data.iter().enumerate() {
+            match item.r#type.as_str() {
+                "track" => track_ids.push(item.id),
+                _ => log::warn!("\tSkipping playlist item ({}): not a track", i + 1),
+            }
+        }
+
+        Ok(Playlist {
+            name: raw_playlist.title.to_owned(),
+            tracks: Self::create_tracks_from_ids(client, config, &track_ids).await,
+            id: raw_playlist.id.to_string(),
+            description: raw_playlist
+                .description
+                .to_owned()
+                .filter(|description| !description.is_empty()),
+            source_service: Source::Deezer,
+            curator: raw_playlist
+                .creator
+                .as_ref()
+                .map(|creator| creator.name.to_owned()),
+            image: raw_playlist.picture_xl.to_owned(),
+        })
+    }
+
+    fn create_service_from_raw(raw_track: &RawTrack, provenance: MatchProvenance) -> Self {
+        let raw_artists: Vec<&RawArtist> = match &raw_track.contributors {
+            Some(contributors) if !contributors.is_empty() => contributors.iter().collect(),
+            _ => vec![&raw_track.artist],
+        };
+
+        Deezer {
+            id: raw_track.id.to_string(),
+            name: raw_track.title.to_owned(),
+            url: raw_track.link.to_owned(),
+            artists: raw_artists
+                .into_iter()
+                .map(|artist| Artist {
+                    id: artist.id.to_string(),
+                    name: artist.name.to_owned(),
+                    url: artist
+                        .link
+                        .to_owned()
+                        .unwrap_or_else(|| format!("https://www.deezer.com/artist/{}", artist.id)),
+                })
+                .collect(),
+            album: Album {
+                id: raw_track.album.id.to_string(),
+                name: raw_track.album.title.to_owned(),
+                url: raw_track.album.link.to_owned().unwrap_or_else(|| {
+                    format!("https://www.deezer.com/album/{}", raw_track.album.id)
+                }),
+                total_tracks: None,
+                ean: None,
+                upc: None,
+            },
+            duration_ms: raw_track.duration * 1000,
+            image: raw_track.album.cover_xl.to_owned(),
+            audio_preview: raw_track
+                .preview
+                .to_owned()
+                .filter(|preview| !preview.is_empty()),
+            provenance: Some(provenance),
+        }
+    }
+
+    fn create_track_from_raw(raw_track: &RawTrack) -> Result<Track> {
+        let (release_year, release_month, release_day) =
+            parse_release_date(raw_track.release_date.as_deref().ok_or_else(|| {
+                Error::missing_field("Deezer", &format!("track/{}", raw_track.id), "release date")
+            })?)?;
+
+        Ok(Track {
+            name: raw_track.title.to_owned(),
+            album: raw_track.album.title.to_owned(),
+            disk_number: raw_track.disk_number.unwrap_or(1),
+            track_number: raw_track.track_position.unwrap_or(1),
+            artists: artist_names(&raw_track.artist, raw_track.contributors.as_deref()),
+            release_year,
+            release_month,
+            release_day,
+            is_explicit: raw_track.explicit_lyrics,
+            duration_ms: raw_track.duration * 1000,
+            services: Services {
+                deezer: Some(Self::create_service_from_raw(
+                    raw_track,
+                    MatchProvenance::direct_id(&raw_track.id.to_string()),
+                )),
+                ..Default::default()
+            },
+            isrc: raw_track.isrc.to_owned(),
+            source_service: Source::Deezer,
+        })
+    }
+}
+
+/// Names of `contributors`, which include featured artists, falling back to
+/// the main `artist`
+fn artist_names(artist: &RawArtist, contributors: Option<&[RawArtist]>) -> Vec<String> {
+    match contributors {
+        Some(contributors) if !contributors.is_empty() => contributors
+            .iter()
+            .map(|contributor| contributor.name.to_owned())
+            .collect(),
+        _ => vec![artist.name.to_owned()],
+    }
+}
+
+impl DeezerProvider {
+    pub fn new() -> Self {
+        Self::with_config(ServiceConfig::deezer())
+    }
+
+    pub fn with_config(config: ServiceConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Default for DeezerProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MusicService for DeezerProvider {
+    fn name(&self) -> &str {
+        "Deezer"
+    }
+
+    fn source(&self) -> Source {
+        Source::Deezer
+    }
+
+    fn storefront(&self) -> Option<&str> {
+        Some(&self.config.storefront)
+    }
+
+    fn service_record(&self, track: &Track) -> Option<Value> {
+        serde_json::to_value(track.services.deezer.as_ref()?).ok()
+    }
+
+    fn set_service_record(&self, track: &mut Track, record: Value) -> Result<()> {
+        track.services.deezer = Some(serde_json::from_value(record)?);
+        Ok(())
+    }
+
+    async fn create_track_from_id(&self, client: &Client, id: &str) -> Result<Track> {
+        Deezer::create_track_from_id(client, &self.config, id).await
+    }
+
+    async fn create_playlist_from_id(&self, client: &Client, id: &str) -> Result<Playlist> {
+        Deezer::create_playlist_from_id(client, &self.config, id).await
+    }
+
+    async fn create_album_from_id(&self, client: &Client, id: &str) -> Result<album::Album> {
+        Deezer::create_album_from_id(client, &self.config, id).await
+    }
+
+    async fn create_service_for_track(&self, client: &Client, track: &mut Track) -> Result<()> {
+        Deezer::create_service_for_track(client, &self.config, track).await
+    }
+
+    async fn create_service_from_id(
+        &self,
+        client: &Client,
+        track: &mut Track,
+        id: &str,
+    ) -> Result<()> {
+        track.services.deezer = Deezer::create_track_from_id(client, &self.config, id)
+            .await?
+            .services
+            .deezer;
+        Ok(())
+    }
+
+    async fn search(&self, client: &Client, track: &Track) -> Result<Vec<MatchReport>> {
+        Deezer::search(client, &self.config, track).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        album::Album,
+        config::ServiceConfig,
+        deezer::{Deezer, DeezerProvider},
+        error::Error,
+        matcher::Matcher,
+        playlist::Playlist,
+        replay::ReplayServer,
+        service::{MatchMethod, Services, Source},
+        track::Track,
+    };
+
+    async fn provider(cassette: &str) -> (ReplayServer, DeezerProvider) {
+        let server: ReplayServer = ReplayServer::start(cassette, ServiceConfig::deezer()).await;
+        let provider: DeezerProvider = DeezerProvider::with_config(server.config());
+        (server, provider)
+    }
+
+    fn example_track(isrc: Option<&str>) -> Track {
+        Track {
+            name: "Duchess for Nothing".to_owned(),
+            album: "Genius Fatigue".to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_owned()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            services: Services::default(),
+            isrc: isrc.map(str::to_owned),
+            source_service: Source::Spotify,
+        }
+    }
+
+    #[tokio::test]
+    async fn get_match_with_isrc() {
+        let mut example_track: Track = example_track(Some("USZUD1215001"));
+
+        let client: reqwest::Client = reqwest::Client::new();
+        let (_server, deezer) = provider("deezer/get_match_with_isrc").await;
+
+        example_track.add_service(&client, &deezer).await.unwrap();
+
+        let deezer = example_track.services.deezer.unwrap();
+        assert_eq!(deezer.id, "2290345681");
+        assert_eq!(deezer.album.name, "Genius Fatigue");
+        assert_eq!(deezer.duration_ms, 138000);
+
+        let provenance = deezer.provenance.unwrap();
+        assert_eq!(provenance.method, MatchMethod::Isrc);
+        assert!(provenance.score.unwrap() >= Matcher::DEFAULT_THRESHOLD);
+    }
+
+    #[tokio::test]
+    async fn get_match_no_isrc() {
+        let mut example_track: Track = example_track(None);
+
+        let client: reqwest::Client = reqwest::Client::new();
+        let (_server, deezer) = provider("deezer/get_match_no_isrc").await;
+
+        example_track.add_service(&client, &deezer).await.unwrap();
+
+        // the first result is a different song
+        let deezer = example_track.services.deezer.unwrap();
+        assert_eq!(deezer.id, "2290345681");
+        assert_eq!(deezer.provenance.unwrap().method, MatchMethod::FuzzySearch);
+    }
+
+    #[test]
+    fn search_query_uses_primary_artist() {
+        let mut example_track: Track = example_track(None);
+        example_track.artists.push("Of Montreal".to_owned());
+
+        assert_eq!(
+            Deezer::search_query(&example_track).unwrap(),
+            "track:\"Duchess for Nothing\" artist:\"Tunabunny\" album:\"Genius Fatigue\""
+        );
+    }
+
+    #[tokio::test]
+    async fn creates_album_from_id() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let (_server, deezer) = provider("deezer/album").await;
+
+        let album: Album = Album::from_service_id(&client, &deezer, "6123456")
+            .await
+            .unwrap();
+
+        assert_eq!(album.name, "Genius Fatigue");
+        assert_eq!(album.upc.as_deref(), Some("656605100113"));
+        assert_eq!(album.label.as_deref(), Some("HHBTM Records"));
+        assert_eq!(album.release_month, Some(2));
+        // the second track is on the next page
+        assert_eq!(album.tracks.len(), 2);
+        assert_eq!(album.tracks[0].isrc.as_deref(), Some("USZUD1215001"));
+        assert_eq!(album.tracks[1].track_number, 2);
+        assert_eq!(album.tracks[1].artists, vec!["Tunabunny", "Guest"]);
+        assert!(album.tracks[1].is_explicit);
+        assert_eq!(album.tracks[1].source_service, Source::Deezer);
+    }
+
+    #[tokio::test]
+    async fn creates_playlist_from_id() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let (_server, deezer) = provider("deezer/playlist").await;
+
+        let playlist: Playlist = Playlist::from_service_id(&client, &deezer, "9876543210")
+            .await
+            .unwrap();
+
+        assert_eq!(playlist.name, "Fixture Mix");
+        assert_eq!(playlist.curator.as_deref(), Some("songvert"));
+        // the episode is skipped and the order kept
+        let names: Vec<&str> = playlist
+            .tracks
+            .iter()
+            .map(|track| track.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Bad Ideas", "Duchess for Nothing"]);
+    }
+
+    #[tokio::test]
+    async fn changed_schema_is_unexpected_response() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let (_server, deezer) = provider("deezer/album_schema_changed").await;
+
+        let result = Album::from_service_id(&client, &deezer, "6123456").await;
+
+        assert!(matches!(
+            result,
+            Err(Error::UnexpectedResponse { path, .. }) if path == "album/6123456"
+        ));
+    }
+}
@@ -3,21 +3,63 @@
 // - https://fettblog.eu/rust-enums-wrapping-errors/
 
 use std::fmt::{Debug, Display};
+use std::time::Duration;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Longest part of a response body kept in `Error::UnexpectedResponse`
+const BODY_SNIPPET_LEN: usize = 200;
+
 #[derive(Debug)]
 pub enum Error {
     DatabaseError(String),
     TrackError(String),
     DownloadError(String),
-    TagError(String),
+    /// Nothing in `service` matched `query`
+    NotFound {
+        service: String,
+        query: String,
+    },
+    /// The service kept answering 429 after every retry
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// The service rejected the token (HTTP 401) or there is no token to send
+    Unauthorized(String),
+    /// The service answered with something that couldn't be parsed, usually
+    /// because its API changed
+    UnexpectedResponse {
+        service: String,
+        path: String,
+        body_snippet: String,
+    },
+    /// A program needed for downloading (e.g. `yt-dlp`) isn't installed
+    ExternalToolMissing(String),
+    /// A URL or option given by the user can't be used
+    InvalidInput(String),
+    Id3Error(id3::Error),
+    Mp4Error(mp4ameta::Error),
     RegexError(regex::Error),
     JsonError(serde_json::Error),
     ParseIntError(std::num::ParseIntError),
     RetrievalError(reqwest::Error),
     IoError(std::io::Error),
-    //PathError(std::path::Pa)
+}
+
+impl Error {
+    /// `UnexpectedResponse` keeping only the start of `body`
+    pub fn unexpected_response(service: &str, path: &str, body: &str) -> Self {
+        Error::UnexpectedResponse {
+            service: service.to_owned(),
+            path: path.to_owned(),
+            body_snippet: body.chars().take(BODY_SNIPPET_LEN).collect(),
+        }
+    }
+
+    /// `UnexpectedResponse` for an object from `path` that has no `field`
+    pub fn missing_field(service: &str, path: &str, field: &str) -> Self {
+        Self::unexpected_response(service, path, &format!("no {}", field))
+    }
 }
 
 impl Display for Error {
@@ -26,28 +68,49 @@ impl Display for Error {
             Error::DatabaseError(s) => write!(f, "Database Error: {}", s),
             Error::TrackError(s) => write!(f, "Track Error: {}", s),
             Error::DownloadError(s) => write!(f, "Download Error: {}", s),
-            Error::TagError(s) => write!(f, "Tag Error: {}", s),
+            Error::NotFound { service, query } => {
+                write!(f, "Not Found: no match for {} on {}", query, service)
+            }
+            Error::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(f, "Rate Limited: retry after {}s", retry_after.as_secs()),
+            Error::RateLimited { retry_after: None } => write!(f, "Rate Limited"),
+            Error::Unauthorized(s) => write!(f, "Unauthorized: {}", s),
+            Error::UnexpectedResponse {
+                service,
+                path,
+                body_snippet,
+            } => write!(
+                f,
+                "Unexpected Response from {} {}: {}",
+                service, path, body_snippet
+            ),
+            Error::ExternalToolMissing(s) => write!(f, "External Tool Missing: {}", s),
+            Error::InvalidInput(s) => write!(f, "Invalid Input: {}", s),
+            Error::Id3Error(e) => write!(f, "ID3 Tag Error: {}", e),
+            Error::Mp4Error(e) => write!(f, "MP4 Tag Error: {}", e),
             Error::JsonError(e) => write!(f, "JSON Parsing Error: {}", e),
             Error::RegexError(e) => write!(f, "Regex Error: {}", e),
             Error::ParseIntError(e) => write!(f, "Integer Parsing Error: {}", e),
-            Error::RetrievalError(e) => write!(f, "Rerieval Error: {}", e),
+            Error::RetrievalError(e) => write!(f, "Retrieval Error: {}", e),
             Error::IoError(e) => write!(f, "IO Error: {}", e),
         }
     }
 }
 
 impl std::error::Error for Error {
-    // fn source(&self) -> Option<&(dyn std::error:Error + 'static)> {
-    //     match *self {
-    //         Error::TrackMatchError => None,
-    //         Error::TrackFindError => None,
-    //         Error::SessionGrabError => None,
-    //         Error::RegexError(ref e) => Some(e),
-    //         Error::ParseError(ref e) => Some(e),
-    //         Error::RetrievalError(ref e) => Some(e),
-    //         Error::IoError(ref e) => Some(e)
-    //     }
-    // }
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Id3Error(e) => Some(e),
+            Error::Mp4Error(e) => Some(e),
+            Error::RegexError(e) => Some(e),
+            Error::JsonError(e) => Some(e),
+            Error::ParseIntError(e) => Some(e),
+            Error::RetrievalError(e) => Some(e),
+            Error::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl From<serde_json::Error> for Error {
@@ -79,8 +142,15 @@ impl From<std::num::ParseIntError> for Error {
         Error::ParseIntError(err)
     }
 }
-// impl From<id3::Error> for Error {
-//     fn from(err: id3::Error) -> Self {
-//         Error::TagError(err)
-//     }
-// }
+
+impl From<id3::Error> for Error {
+    fn from(err: id3::Error) -> Self {
+        Error::Id3Error(err)
+    }
+}
+
+impl From<mp4ameta::Error> for Error {
+    fn from(err: mp4ameta::Error) -> Self {
+        Error::Mp4Error(err)
+    }
+}
@@ -1,8 +1,18 @@
 pub mod album;
 pub mod apple_music;
+pub mod auth;
 pub mod bandcamp;
+pub mod cache;
+pub mod config;
+pub mod deezer;
 pub mod error;
+pub mod matcher;
+pub mod normalize;
+pub mod overrides;
 pub mod playlist;
+#[cfg(test)]
+mod replay;
+pub mod request;
 pub mod service;
 pub mod spotify;
 pub mod track;
@@ -1,15 +1,19 @@
 mod cli;
 
-use crate::cli::Cli;
+use crate::cli::{exit_code, Cli};
 use clap::Parser;
-use songvert::error::Result;
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     std::env::set_var("RUST_BACKTRACE", "full");
 
     let cli = Cli::parse();
-    cli.run().await?;
-
-    Ok(())
+    match cli.run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
 }
@@ -0,0 +1,626 @@
+use crate::normalize::{artist_keys, fold, split_title, title_key, TitleParts};
+use crate::track::Track;
+use std::fmt::Display;
+
+/// What a search result is known to be, compared against a `Track` by `Matcher`.
+/// Fields the service doesn't return are left `None` and skipped when scoring.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Candidate {
+    /// Id of the result in its service
+    pub id: String,
+    pub url: String,
+    pub name: String,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    pub duration_ms: Option<usize>,
+    pub isrc: Option<String>,
+    pub is_explicit: Option<bool>,
+    pub release_year: Option<usize>,
+}
+
+/// Something `Matcher` compares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Title,
+    Artists,
+    Album,
+    Duration,
+    Isrc,
+    Explicit,
+    Year,
+}
+
+/// How much each `Feature` counts towards the score
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchWeights {
+    pub title: f64,
+    pub artists: f64,
+    pub album: f64,
+    pub duration: f64,
+    pub isrc: f64,
+    pub explicit: f64,
+    pub year: f64,
+}
+
+/// Which version of a track to look for when a service has both an explicit
+/// and a clean one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExplicitPreference {
+    /// The version the source track is
+    #[default]
+    Same,
+    Clean,
+    Explicit,
+}
+
+/// Scores candidates against a track. Every feature both sides know about gets a
+/// similarity between 0 and 1, and the score is the weighted mean of those, so
+/// missing data neither helps nor hurts a candidate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matcher {
+    pub weights: MatchWeights,
+    /// Lowest score accepted as a match
+    pub threshold: f64,
+    /// Which version scores the `Explicit` feature
+    pub explicit_preference: ExplicitPreference,
+}
+
+/// How one `Feature` contributed to a `MatchReport`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureScore {
+    pub feature: Feature,
+    /// Between 0 (nothing alike) and 1 (identical)
+    pub similarity: f64,
+    pub weight: f64,
+}
+
+/// Why a candidate did or didn't match
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchReport {
+    pub candidate: Candidate,
+    /// Weighted mean of every feature's similarity
+    pub score: f64,
+    pub threshold: f64,
+    pub features: Vec<FeatureScore>,
+}
+
+impl Feature {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::Title => "title",
+            Feature::Artists => "artists",
+            Feature::Album => "album",
+            Feature::Duration => "duration",
+            Feature::Isrc => "isrc",
+            Feature::Explicit => "explicit",
+            Feature::Year => "year",
+        }
+    }
+}
+
+impl Default for MatchWeights {
+    fn default() -> Self {
+        MatchWeights {
+            title: 1.0,
+            artists: 1.0,
+            album: 1.0,
+            duration: 1.0,
+            isrc: 1.0,
+            explicit: 0.25,
+            year: 0.25,
+        }
+    }
+}
+
+impl MatchWeights {
+    fn get(&self, feature: Feature) -> f64 {
+        match feature {
+            Feature::Title => self.title,
+            Feature::Artists => self.artists,
+            Feature::Album => self.album,
+            Feature::Duration => self.duration,
+            Feature::Isrc => self.isrc,
+            Feature::Explicit => self.explicit,
+            Feature::Year => self.year,
+        }
+    }
+}
+
+impl ExplicitPreference {
+    /// Whether the explicit version of `track` is wanted
+    pub fn wants_explicit(&self, track: &Track) -> bool {
+        match self {
+            ExplicitPreference::Same => track.is_explicit,
+            ExplicitPreference::Clean => false,
+            ExplicitPreference::Explicit => true,
+        }
+    }
+}
+
+impl Default for Matcher {
+    fn default() -> Self {
+        Matcher::new(Matcher::DEFAULT_THRESHOLD)
+    }
+}
+
+impl Matcher {
+    pub const DEFAULT_THRESHOLD: f64 = 0.75;
+    /// Share of the title similarity kept when the versions differ, e.g. `Live`
+    const VERSION_MISMATCH_FACTOR: f64 = 0.8;
+    /// Artist names less alike than this count as different artists
+    const ARTIST_SIMILARITY_FLOOR: f64 = 0.85;
+    /// Durations this close (in ms) count as identical
+    const DURATION_TOLERANCE_MS: usize = 3000;
+    /// Durations this far apart (in ms) or more count as nothing alike
+    const DURATION_LIMIT_MS: usize = 15000;
+
+    pub fn new(threshold: f64) -> Self {
+        Matcher {
+            weights: MatchWeights::default(),
+            threshold,
+            explicit_preference: ExplicitPreference::default(),
+        }
+    }
+
+    pub fn with_weights(mut self, weights: MatchWeights) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_explicit_preference(mut self, explicit_preference: ExplicitPreference) -> Self {
+        self.explicit_preference = explicit_preference;
+        self
+    }
+
+    /// `track`'s ISRC, unless it identifies the other version than the one
+    /// `explicit_preference` asks for, which can only be found by searching
+    pub fn wanted_isrc<'a>(&self, track: &'a Track) -> Option<&'a String> {
+        track
+            .isrc
+            .as_ref()
+            .filter(|_| self.explicit_preference.wants_explicit(track) == track.is_explicit)
+    }
+
+    /// Score `candidate` against `track`
+    pub fn score(&self, track: &Track, candidate: &Candidate) -> MatchReport {
+        let track_title: TitleParts = split_title(&track.name);
+        let candidate_title: TitleParts = split_title(&candidate.name);
+
+        let mut title_similarity: f64 = text_similarity(&track_title.title, &candidate_title.title);
+        if track_title.version_key() != candidate_title.version_key() {
+            title_similarity *= Self::VERSION_MISMATCH_FACTOR;
+        }
+
+        let mut similarities: Vec<(Feature, f64)> = vec![
+            (Feature::Title, title_similarity),
+            (
+                Feature::Artists,
+                artists_similarity(
+                    &artist_keys(&track.artists, &track_title.featured_artists),
+                    &artist_keys(&candidate.artists, &candidate_title.featured_artists),
+                ),
+            ),
+        ];
+
+        if let Some(album) = &candidate.album {
+            similarities.push((
+                Feature::Album,
+                strsim::jaro_winkler(&title_key(&track.album), &title_key(album)),
+            ));
+        }
+
+        if let Some(duration_ms) = candidate.duration_ms {
+            similarities.push((
+                Feature::Duration,
+                duration_similarity(track.duration_ms, duration_ms),
+            ));
+        }
+
+        if let (Some(isrc), Some(candidate_isrc)) = (&track.isrc, &candidate.isrc) {
+            similarities.push((
+                Feature::Isrc,
+                exact_similarity(isrc.eq_ignore_ascii_case(candidate_isrc)),
+            ));
+        }
+
+        if let Some(is_explicit) = candidate.is_explicit {
+            similarities.push((
+                Feature::Explicit,
+                exact_similarity(self.explicit_preference.wants_explicit(track) == is_explicit),
+            ));
+        }
+
+        if let Some(release_year) = candidate.release_year {
+            similarities.push((
+                Feature::Year,
+                match track.release_year.abs_diff(release_year) {
+                    0 => 1.0,
+                    1 => 0.5,
+                    _ => 0.0,
+                },
+            ));
+        }
+
+        let features: Vec<FeatureScore> = similarities
+            .into_iter()
+            .map(|(feature, similarity)| FeatureScore {
+                feature,
+                similarity,
+                weight: self.weights.get(feature),
+            })
+            .collect();
+
+        let total_weight: f64 = features.iter().map(|f| f.weight).sum();
+        let score: f64 = if total_weight > 0.0 {
+            features.iter().map(FeatureScore::contribution).sum::<f64>() / total_weight
+        } else {
+            0.0
+        };
+
+        MatchReport {
+            candidate: candidate.clone(),
+            score,
+            threshold: self.threshold,
+            features,
+        }
+    }
+
+    /// Score every candidate against `track`, best first. Each candidate travels
+    /// with `T`, usually the raw result it was built from.
+    pub fn rank<T>(&self, track: &Track, candidates: Vec<(Candidate, T)>) -> Vec<(MatchReport, T)> {
+        let mut ranked: Vec<(MatchReport, T)> = candidates
+            .into_iter()
+            .map(|(candidate, item)| (self.score(track, &candidate), item))
+            .collect();
+        ranked.sort_by(|(a, _), (b, _)| b.score.total_cmp(&a.score));
+
+        for (report, _) in &ranked {
+            log::debug!("{}", report);
+        }
+        ranked
+    }
+
+    /// Best of `ranked` (as returned by `rank`) if it scores at least `threshold`
+    pub fn best_match<T>(ranked: Vec<(MatchReport, T)>) -> Option<(MatchReport, T)> {
+        ranked
+            .into_iter()
+            .next()
+            .filter(|(report, _)| report.is_match())
+    }
+}
+
+impl FeatureScore {
+    /// Weighted similarity, i.e. how much this feature adds to the total
+    pub fn contribution(&self) -> f64 {
+        self.similarity * self.weight
+    }
+}
+
+impl MatchReport {
+    pub fn is_match(&self) -> bool {
+        self.score >= self.threshold
+    }
+
+    /// Whether the candidate is known to be the other version than the one
+    /// `ExplicitPreference` asked for
+    pub fn is_other_version(&self) -> bool {
+        self.features
+            .iter()
+            .any(|f| f.feature == Feature::Explicit && f.similarity == 0.0)
+    }
+}
+
+impl Display for MatchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} - {} <{}>: {:.3} ({} {:.3})",
+            self.candidate.name,
+            self.candidate.artists.join(", "),
+            self.candidate.url,
+            self.score,
+            if self.is_match() { ">=" } else { "<" },
+            self.threshold
+        )?;
+        for feature in &self.features {
+            write!(
+                f,
+                "\n\t{}: {:.3} x {:.2} = {:.3}",
+                feature.feature.name(),
+                feature.similarity,
+                feature.weight,
+                feature.contribution()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn text_similarity(a: &str, b: &str) -> f64 {
+    strsim::jaro_winkler(&fold(a), &fold(b))
+}
+
+/// Overlap of two artist sets regardless of order: every artist is paired with
+/// its most similar unpaired artist on the other side, and the similarities of
+/// the pairs count twice against the size of both sets (the Dice coefficient).
+/// Artists split differently on each side, e.g. `Simon and Garfunkel` against
+/// `Simon` and `Garfunkel`, are identical.
+fn artists_similarity(a: &[String], b: &[String]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let words = |keys: &[String]| -> Vec<String> {
+        keys.iter()
+            .flat_map(|key| key.split(' '))
+            .filter(|word| *word != "and")
+            .map(str::to_owned)
+            .collect()
+    };
+    if words(a) == words(b) {
+        return 1.0;
+    }
+
+    let (fewer, more) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let mut unpaired: Vec<&String> = more.iter().collect();
+    let mut paired_similarity: f64 = 0.0;
+    for artist in fewer {
+        let best = unpaired
+            .iter()
+            .enumerate()
+            .map(|(i, other)| (i, strsim::jaro_winkler(artist, other)))
+            .max_by(|(_, x), (_, y)| x.total_cmp(y));
+        if let Some((i, similarity)) = best {
+            if similarity >= Matcher::ARTIST_SIMILARITY_FLOOR {
+                paired_similarity += similarity;
+                unpaired.remove(i);
+            }
+        }
+    }
+    2.0 * paired_similarity / (a.len() + b.len()) as f64
+}
+
+fn duration_similarity(a_ms: usize, b_ms: usize) -> f64 {
+    let difference: usize = a_ms.abs_diff(b_ms);
+    if difference <= Matcher::DURATION_TOLERANCE_MS {
+        1.0
+    } else if difference >= Matcher::DURATION_LIMIT_MS {
+        0.0
+    } else {
+        (Matcher::DURATION_LIMIT_MS - difference) as f64
+            / (Matcher::DURATION_LIMIT_MS - Matcher::DURATION_TOLERANCE_MS) as f64
+    }
+}
+
+fn exact_similarity(equal: bool) -> f64 {
+    if equal {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        matcher::{Candidate, ExplicitPreference, Feature, Matcher},
+        service::{Services, Source},
+        track::Track,
+    };
+
+    fn track() -> Track {
+        Track {
+            name: "Duchess for Nothing".to_string(),
+            album: "Genius Fatigue".to_string(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_string()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            isrc: Some("USZUD1215001".to_string()),
+            services: Services::default(),
+            source_service: Source::Spotify,
+        }
+    }
+
+    #[test]
+    fn identical_candidate_matches() {
+        let report = Matcher::default().score(
+            &track(),
+            &Candidate {
+                name: "Duchess for Nothing".to_string(),
+                artists: vec!["Tunabunny".to_string()],
+                album: Some("Genius Fatigue".to_string()),
+                duration_ms: Some(138000),
+                isrc: Some("USZUD1215001".to_string()),
+                is_explicit: Some(false),
+                release_year: Some(2013),
+                ..Default::default()
+            },
+        );
+
+        assert!(report.is_match());
+        assert_eq!(report.score, 1.0);
+        assert_eq!(report.features.len(), 7);
+    }
+
+    #[test]
+    fn missing_features_are_skipped() {
+        let report = Matcher::default().score(
+            &track(),
+            &Candidate {
+                name: "Duchess for Nothing".to_string(),
+                artists: vec!["Tunabunny".to_string()],
+                album: Some("Genius Fatigue".to_string()),
+                ..Default::default()
+            },
+        );
+
+        assert!(report.is_match());
+        assert!(report
+            .features
+            .iter()
+            .all(|f| [Feature::Title, Feature::Artists, Feature::Album].contains(&f.feature)));
+    }
+
+    #[test]
+    fn titles_and_artists_are_normalized() {
+        let report = Matcher::default().score(
+            &track(),
+            &Candidate {
+                name: "DUCHESS FOR NOTHING - 2013 Remaster".to_string(),
+                artists: vec!["The Tunabunny".to_string()],
+                album: Some("Genius Fatigue (Deluxe Edition)".to_string()),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(report.score, 1.0);
+
+        let live = Matcher::default().score(
+            &track(),
+            &Candidate {
+                name: "Duchess for Nothing (Live)".to_string(),
+                ..report.candidate
+            },
+        );
+
+        assert!(live.score < report.score);
+    }
+
+    #[test]
+    fn different_track_does_not_match() {
+        let report = Matcher::default().score(
+            &track(),
+            &Candidate {
+                name: "Airplane Wrist".to_string(),
+                artists: vec!["Tunabunny".to_string()],
+                album: Some("Kingdom Technology".to_string()),
+                duration_ms: Some(201000),
+                isrc: Some("USZUD1615003".to_string()),
+                is_explicit: Some(false),
+                release_year: Some(2017),
+                ..Default::default()
+            },
+        );
+
+        assert!(!report.is_match());
+        let duration = report
+            .features
+            .iter()
+            .find(|f| f.feature == Feature::Duration)
+            .unwrap();
+        assert_eq!(duration.contribution(), 0.0);
+    }
+
+    #[test]
+    fn ranks_best_candidate_first() {
+        let matcher = Matcher::default();
+        let ranked = matcher.rank(
+            &track(),
+            vec![
+                (
+                    Candidate {
+                        name: "Duchess for Nothing (Live)".to_string(),
+                        artists: vec!["Tunabunny".to_string()],
+                        ..Default::default()
+                    },
+                    "live",
+                ),
+                (
+                    Candidate {
+                        name: "Duchess for Nothing".to_string(),
+                        artists: vec!["Tunabunny".to_string()],
+                        ..Default::default()
+                    },
+                    "studio",
+                ),
+            ],
+        );
+
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(
+            Matcher::best_match(ranked).map(|(_, item)| item),
+            Some("studio")
+        );
+    }
+
+    #[test]
+    fn prefers_requested_explicitness() {
+        let explicit_track = Track {
+            is_explicit: true,
+            ..track()
+        };
+        let candidates = || {
+            [false, true].map(|is_explicit| {
+                (
+                    Candidate {
+                        name: "Duchess for Nothing".to_string(),
+                        artists: vec!["Tunabunny".to_string()],
+                        is_explicit: Some(is_explicit),
+                        ..Default::default()
+                    },
+                    is_explicit,
+                )
+            })
+        };
+
+        let (report, is_explicit) =
+            Matcher::best_match(Matcher::default().rank(&explicit_track, candidates().into()))
+                .unwrap();
+        assert!(is_explicit);
+        assert!(!report.is_other_version());
+
+        let clean = Matcher::default().with_explicit_preference(ExplicitPreference::Clean);
+        let (_, is_explicit) =
+            Matcher::best_match(clean.rank(&explicit_track, candidates().into())).unwrap();
+        assert!(!is_explicit);
+
+        // only the clean version exists
+        let (report, _) = Matcher::best_match(
+            Matcher::default().rank(&explicit_track, vec![candidates()[0].clone()]),
+        )
+        .unwrap();
+        assert!(report.is_match());
+        assert!(report.is_other_version());
+    }
+
+    #[test]
+    fn artists_are_compared_as_sets() {
+        let duet = Track {
+            artists: vec!["Tunabunny".to_string(), "Jane Doe".to_string()],
+            ..track()
+        };
+        let artists_score = |artists: &[&str]| {
+            Matcher::default()
+                .score(
+                    &duet,
+                    &Candidate {
+                        name: "Duchess for Nothing".to_string(),
+                        artists: artists.iter().map(|a| a.to_string()).collect(),
+                        ..Default::default()
+                    },
+                )
+                .features
+                .iter()
+                .find(|f| f.feature == Feature::Artists)
+                .unwrap()
+                .similarity
+        };
+
+        assert_eq!(artists_score(&["Jane Doe", "The Tunabunny"]), 1.0);
+        assert_eq!(artists_score(&["Tunabunny & Jane Doe"]), 1.0);
+        let partial: f64 = artists_score(&["Tunabunny"]);
+        assert!(partial > 0.6 && partial < 1.0);
+        assert!(artists_score(&["Tunabunny", "John Roe"]) <= partial);
+        assert_eq!(artists_score(&["Other Band"]), 0.0);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::error::{Error, Result};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Words that mark a bracketed or dashed part of a title as a version, e.g.
+/// `Song (Live)` or `Song - 2011 Remaster`
+const VERSION_WORDS: [&str; 20] = [
+    "acoustic",
+    "anniversary",
+    "bonus",
+    "deluxe",
+    "demo",
+    "edit",
+    "edition",
+    "extended",
+    "instrumental",
+    "live",
+    "mix",
+    "mono",
+    "remaster",
+    "remastered",
+    "remix",
+    "radio",
+    "rework",
+    "stereo",
+    "unplugged",
+    "version",
+];
+
+/// Version words that don't change the recording
+const REMASTER_WORDS: [&str; 2] = ["remaster", "remastered"];
+
+/// What introduces featured artists, either inside brackets or after the title
+const FEATURE_MARKERS: [&str; 6] = ["featuring ", "feat. ", "feat ", "ft. ", "ft ", "with "];
+
+/// What separates artists credited together, e.g. `Artist & Other`
+const ARTIST_SEPARATORS: [&str; 6] = [", ", " & ", " featuring ", " feat. ", " ft. ", " vs. "];
+
+/// A track or album title split into the parts that matter for matching
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TitleParts {
+    /// Title without featured artists or version tags
+    pub title: String,
+    /// Artists credited in the title, e.g. `X` in `Song (feat. X)`
+    pub featured_artists: Vec<String>,
+    /// Version tags, e.g. `Live` or `2011 Remaster`, joined with `, `
+    pub version: Option<String>,
+}
+
+impl TitleParts {
+    /// Folded `version` without remaster tags and years, which don't make a
+    /// different recording. `None` means the original version.
+    pub fn version_key(&self) -> Option<String> {
+        let version: String = fold(self.version.as_deref()?)
+            .split(' ')
+            .filter(|word| {
+                !REMASTER_WORDS.contains(word) && !word.chars().all(|c| c.is_ascii_digit())
+            })
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        if version.is_empty() {
+            None
+        } else {
+            Some(version)
+        }
+    }
+}
+
+/// Split featured artists and version tags off `title`, e.g.
+/// `Song (feat. X) - 2011 Remaster` becomes `Song`, `[X]` and `2011 Remaster`.
+/// Brackets that are neither, like `(I Can't Get No) Satisfaction`, are kept.
+pub fn split_title(title: &str) -> TitleParts {
+    let mut parts = TitleParts::default();
+    let mut versions: Vec<String> = Vec::new();
+    let mut rest: &str = title;
+
+    while let Some(open) = rest.find(['(', '[']) {
+        let Some(close) = rest[open..].find([')', ']']).map(|i| open + i) else {
+            break;
+        };
+
+        parts.title.push_str(&rest[..open]);
+        let inner: &str = rest[open + 1..close].trim();
+        if let Some(artists) = featured_artists(inner) {
+            parts.featured_artists.extend(artists);
+        } else if is_version(inner) {
+            versions.push(inner.to_owned());
+        } else {
+            parts.title.push_str(&rest[open..=close]);
+        }
+        rest = &rest[close + 1..];
+    }
+    parts.title.push_str(rest);
+
+    if let Some((head, tail)) = parts.title.rsplit_once(" - ") {
+        if let Some(artists) = featured_artists(tail.trim()) {
+            parts.featured_artists.extend(artists);
+            parts.title = head.to_owned();
+        } else if is_version(tail) {
+            versions.insert(0, tail.trim().to_owned());
+            parts.title = head.to_owned();
+        }
+    }
+
+    // a bare `with` is too often part of the title, e.g. `Dancing with Myself`
+    let lowercase_title: String = parts.title.to_ascii_lowercase();
+    if let Some(i) = FEATURE_MARKERS[..5]
+        .iter()
+        .filter_map(|marker| lowercase_title.find(&format!(" {}", marker)))
+        .min()
+    {
+        if let Some(artists) = featured_artists(parts.title[i..].trim()) {
+            parts.featured_artists.extend(artists);
+            parts.title.truncate(i);
+        }
+    }
+
+    parts.title = collapse_whitespace(&parts.title);
+    if !versions.is_empty() {
+        parts.version = Some(versions.join(", "));
+    }
+    parts
+}
+
+/// Split artists credited together, e.g. `A, B & C` into `A`, `B` and `C`
+pub fn split_artists(artists: &str) -> Vec<String> {
+    let mut split: Vec<String> = vec![artists.to_owned()];
+    for separator in ARTIST_SEPARATORS {
+        split = split
+            .iter()
+            .flat_map(|artist| split_ignore_ascii_case(artist, separator))
+            .map(str::to_owned)
+            .collect();
+    }
+
+    split
+        .into_iter()
+        .map(|artist| artist.trim().to_owned())
+        .filter(|artist| !artist.is_empty())
+        .collect()
+}
+
+/// Lowercase ASCII with diacritics and punctuation removed, `&` spelled out and
+/// whitespace collapsed, so `Beyoncé & Jay-Z` and `beyonce and jay z` are equal
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.nfkd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            '&' => folded.push_str(" and "),
+            '\'' | '\u{2019}' => (),
+            'ß' => folded.push_str("ss"),
+            'æ' | 'Æ' => folded.push_str("ae"),
+            'œ' | 'Œ' => folded.push_str("oe"),
+            'ø' | 'Ø' => folded.push('o'),
+            'ł' | 'Ł' => folded.push('l'),
+            'đ' | 'Đ' => folded.push('d'),
+            c if c.is_alphanumeric() => folded.extend(c.to_lowercase()),
+            _ => folded.push(' '),
+        }
+    }
+    collapse_whitespace(&folded)
+}
+
+/// Folded title without featured artists or version tags
+pub fn title_key(title: &str) -> String {
+    fold(&split_title(title).title)
+}
+
+/// Folded artist name without a leading `The` (or a trailing `, The`)
+pub fn artist_key(artist: &str) -> String {
+    let folded: String = fold(artist);
+    let key: &str = folded.strip_prefix("the ").unwrap_or(&folded);
+    let key: &str = key.strip_suffix(" the").unwrap_or(key);
+    key.to_owned()
+}
+
+/// Keys of every artist in `artists` and `featured_artists`, split and deduplicated
+pub fn artist_keys(artists: &[String], featured_artists: &[String]) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    for artist in artists.iter().flat_map(|a| split_artists(a)) {
+        let key: String = artist_key(&artist);
+        if !key.is_empty() && !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    for artist in featured_artists {
+        let key: String = artist_key(artist);
+        if !key.is_empty() && !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+/// `text` made safe for a search query: diacritics and punctuation removed, but
+/// case kept
+pub fn search_text(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    for c in text.nfkd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            '\'' | '\u{2019}' => (),
+            c if c.is_alphanumeric() => cleaned.push(c),
+            _ => cleaned.push(' '),
+        }
+    }
+    collapse_whitespace(&cleaned)
+}
+
+/// Every one of `artists` made safe for a search query, e.g. `Artist Other`
+pub fn search_artists(artists: &[String]) -> Result<String> {
+    if artists.is_empty() {
+        return Err(Error::TrackError(
+            "Track requires at least one artist".to_string(),
+        ));
+    }
+    Ok(search_text(&artists.join(" ")))
+}
+
+/// Title to search for, without featured artists or version tags
+pub fn search_title(title: &str) -> String {
+    search_text(&split_title(title).title)
+}
+
+fn featured_artists(text: &str) -> Option<Vec<String>> {
+    let lowercase_text: String = text.to_ascii_lowercase();
+    FEATURE_MARKERS
+        .iter()
+        .find(|marker| lowercase_text.starts_with(*marker))
+        .map(|marker| split_artists(&text[marker.len()..]))
+}
+
+fn is_version(text: &str) -> bool {
+    fold(text)
+        .split(' ')
+        .any(|word| VERSION_WORDS.contains(&word))
+}
+
+fn split_ignore_ascii_case<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+    let lowercase_text: String = text.to_ascii_lowercase();
+    let mut split: Vec<&str> = Vec::new();
+    let mut start: usize = 0;
+    for (i, _) in lowercase_text.match_indices(separator) {
+        split.push(&text[start..i]);
+        start = i + separator.len();
+    }
+    split.push(&text[start..]);
+    split
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::normalize::{artist_keys, fold, search_title, split_title, TitleParts};
+
+    #[test]
+    fn splits_featured_artists_and_versions() {
+        assert_eq!(
+            split_title("Duchess for Nothing (feat. Jane Doe & John Roe) - 2011 Remaster"),
+            TitleParts {
+                title: "Duchess for Nothing".to_string(),
+                featured_artists: vec!["Jane Doe".to_string(), "John Roe".to_string()],
+                version: Some("2011 Remaster".to_string()),
+            }
+        );
+        assert_eq!(split_title("Song [Live]").version, Some("Live".to_string()));
+        assert_eq!(
+            split_title("Song [Live]").version_key(),
+            Some("live".to_string())
+        );
+        assert_eq!(split_title("Song - 2011 Remaster").version_key(), None);
+        assert_eq!(
+            split_title("Song ft. X").featured_artists,
+            vec!["X".to_string()]
+        );
+        assert_eq!(
+            split_title("(I Can't Get No) Satisfaction").title,
+            "(I Can't Get No) Satisfaction"
+        );
+    }
+
+    #[test]
+    fn folds_text_and_artists() {
+        assert_eq!(fold("Beyoncé & JAY-Z"), "beyonce and jay z");
+        assert_eq!(
+            fold("Sigur Rós – Ágætis byrjun"),
+            "sigur ros agaetis byrjun"
+        );
+        assert_eq!(
+            artist_keys(
+                &["The Tunabunny, Other Band".to_string()],
+                &["Tunabunny".to_string()]
+            ),
+            vec!["tunabunny".to_string(), "other band".to_string()]
+        );
+        assert_eq!(search_title("Don't Stop (feat. X) [Live]"), "Dont Stop");
+    }
+}
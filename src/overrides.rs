@@ -0,0 +1,406 @@
+//! Manual match overrides.
+//!
+//! When a service keeps matching a track wrongly, the right record can be
+//! pinned in a JSON file (by default in the XDG config directory) mapping a
+//! source identifier to a forced id, or to `"skip"`, per service:
+//!
+//! ```json
+//! {
+//!   "isrc:USZUD1215001": { "YouTube": { "id": "bS3kqv1uQ5E" } },
+//!   "Spotify:6Jd9HwGbIUVjQpHwQ0R9yL": { "Bandcamp": "skip" }
+//! }
+//! ```
+//!
+//! Wrapping a service in `OverriddenService` makes `Track`, `Playlist` and
+//! `Album` consult the file before searching. Their `add_*` helpers do this
+//! with `Overrides::shared_default`.
+
+use crate::album;
+use crate::cache::{CachedService, MatchCache};
+use crate::error::Result;
+use crate::matcher::MatchReport;
+use crate::playlist::Playlist;
+use crate::service::{MusicService, Source};
+use crate::track::Track;
+use crate::utils::config_dir;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+/// What to do instead of searching a service for a track
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Override {
+    /// Never add the service to the track
+    Skip,
+    /// Use the record with this id (for Bandcamp, the track URL)
+    Id(String),
+}
+
+/// Overrides keyed by source identifier (see `Overrides::isrc_key` and
+/// `Overrides::id_key`), then by `MusicService::name`
+#[derive(Debug)]
+pub struct Overrides {
+    path: PathBuf,
+    entries: BTreeMap<String, BTreeMap<String, Override>>,
+}
+
+impl Overrides {
+    /// `overrides.json` in `utils::config_dir`
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("overrides.json"))
+    }
+
+    /// Load the overrides at `path`. A missing file has none, but an invalid one
+    /// is an error since it was written by hand.
+    pub fn open(path: &Path) -> Result<Self> {
+        let entries: BTreeMap<String, BTreeMap<String, Override>> =
+            match std::fs::read_to_string(path) {
+                Ok(raw) => serde_json::from_str(&raw)?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => return Err(e.into()),
+            };
+
+        Ok(Overrides {
+            path: path.to_owned(),
+            entries,
+        })
+    }
+
+    /// The overrides at `default_path`. Unlike `open`, a missing config directory
+    /// or an unreadable file only logs a warning and leaves no overrides, since
+    /// the caller never asked for them.
+    pub fn open_default() -> Self {
+        let path: PathBuf = match Self::default_path() {
+            Some(path) => path,
+            None => {
+                log::warn!("Unable to find config directory, not using match overrides");
+                return Overrides {
+                    path: PathBuf::new(),
+                    entries: BTreeMap::new(),
+                };
+            }
+        };
+
+        Self::open(&path).unwrap_or_else(|e| {
+            log::warn!(
+                "\tIgnoring match overrides {}: {}",
+                path.to_string_lossy(),
+                e
+            );
+            Overrides {
+                path,
+                entries: BTreeMap::new(),
+            }
+        })
+    }
+
+    /// `open_default`, loaded on first use and shared by every later caller
+    pub fn shared_default() -> Arc<Self> {
+        static SHARED: OnceLock<Arc<Overrides>> = OnceLock::new();
+        SHARED
+            .get_or_init(|| Arc::new(Self::open_default()))
+            .clone()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Source identifier of a track by its ISRC
+    pub fn isrc_key(isrc: &str) -> String {
+        format!("isrc:{}", isrc.trim().to_uppercase())
+    }
+
+    /// Source identifier of a track by its id in `service`, e.g. `Spotify:6Jd9…`
+    pub fn id_key(service: &str, id: &str) -> String {
+        format!("{}:{}", service, id)
+    }
+
+    /// Override of `service` for `track`, by ISRC first and service ids second
+    pub fn get(&self, service: &str, track: &Track) -> Option<&Override> {
+        source_keys(track)
+            .iter()
+            .find_map(|key| self.entries.get(key)?.get(service))
+    }
+
+    /// Pin `service` to `value` for the track identified by `source_key`
+    pub fn insert(&mut self, source_key: &str, service: &str, value: Override) {
+        self.entries
+            .entry(source_key.to_owned())
+            .or_default()
+            .insert(service.to_owned(), value);
+    }
+
+    /// Write the overrides to `path`
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.entries)?)?;
+        Ok(())
+    }
+}
+
+/// `MusicService` that applies `Overrides` in `create_service_for_track(s)` and
+/// leaves every other track to `service`
+pub struct OverriddenService {
+    service: Box<dyn MusicService>,
+    overrides: Arc<Overrides>,
+}
+
+impl OverriddenService {
+    pub fn new(service: Box<dyn MusicService>, overrides: Arc<Overrides>) -> Self {
+        OverriddenService { service, overrides }
+    }
+
+    /// Apply the override for `track`, returning `None` if it has none
+    async fn add_overridden(&self, client: &Client, track: &mut Track) -> Option<Result<()>> {
+        match self.overrides.get(self.service.name(), track)? {
+            Override::Skip => {
+                log::info!(
+                    "Skipping {} for {} (overridden)",
+                    self.service.name(),
+                    track.name
+                );
+                Some(Ok(()))
+            }
+            Override::Id(id) => Some(self.service.create_service_from_id(client, track, id).await),
+        }
+    }
+}
+
+#[async_trait]
+impl MusicService for OverriddenService {
+    fn name(&self) -> &str {
+        self.service.name()
+    }
+
+    fn source(&self) -> Source {
+        self.service.source()
+    }
+
+    fn storefront(&self) -> Option<&str> {
+        self.service.storefront()
+    }
+
+    fn service_record(&self, track: &Track) -> Option<Value> {
+        self.service.service_record(track)
+    }
+
+    fn set_service_record(&self, track: &mut Track, record: Value) -> Result<()> {
+        self.service.set_service_record(track, record)
+    }
+
+    async fn create_track_from_id(&self, client: &Client, id: &str) -> Result<Track> {
+        self.service.create_track_from_id(client, id).await
+    }
+
+    async fn create_playlist_from_id(&self, client: &Client, id: &str) -> Result<Playlist> {
+        self.service.create_playlist_from_id(client, id).await
+    }
+
+    async fn create_album_from_id(&self, client: &Client, id: &str) -> Result<album::Album> {
+        self.service.create_album_from_id(client, id).await
+    }
+
+    async fn search(&self, client: &Client, track: &Track) -> Result<Vec<MatchReport>> {
+        self.service.search(client, track).await
+    }
+
+    async fn create_service_from_id(
+        &self,
+        client: &Client,
+        track: &mut Track,
+        id: &str,
+    ) -> Result<()> {
+        self.service.create_service_from_id(client, track, id).await
+    }
+
+    async fn create_service_for_track(&self, client: &Client, track: &mut Track) -> Result<()> {
+        match self.add_overridden(client, track).await {
+            Some(result) => result,
+            None => self.service.create_service_for_track(client, track).await,
+        }
+    }
+
+    async fn create_services_for_tracks(
+        &self,
+        client: &Client,
+        tracks: &mut [Track],
+    ) -> Result<()> {
+        let mut remaining: Vec<usize> = Vec::new();
+        for (i, track) in tracks.iter_mut().enumerate() {
+            match self.add_overridden(client, track).await {
+                Some(Err(e)) => log::warn!(
+                    "\tSkipping adding {} to track ({}) {}: {}",
+                    self.service.name(),
+                    i + 1,
+                    track.name,
+                    e
+                ),
+                Some(Ok(())) => (),
+                None => remaining.push(i),
+            }
+        }
+
+        if remaining.is_empty() {
+            return Ok(());
+        }
+
+        // same trick as `CachedService`: batch the remaining tracks at the front
+        for (i, j) in remaining.iter().enumerate() {
+            tracks.swap(i, *j);
+        }
+        let result: Result<()> = self
+            .service
+            .create_services_for_tracks(client, &mut tracks[..remaining.len()])
+            .await;
+        for (i, j) in remaining.iter().enumerate().rev() {
+            tracks.swap(i, *j);
+        }
+
+        result
+    }
+
+    async fn download(
+        &self,
+        client: &Client,
+        track: &Track,
+        path: &Path,
+        filename: &str,
+    ) -> Result<PathBuf> {
+        self.service.download(client, track, path, filename).await
+    }
+}
+
+/// `service` answering from `cache`, when there is one, with `overrides`
+/// applied on top so they win over cached matches
+pub fn with_overrides_and_cache(
+    service: Box<dyn MusicService>,
+    overrides: Arc<Overrides>,
+    cache: Option<Arc<MatchCache>>,
+) -> OverriddenService {
+    let service: Box<dyn MusicService> = match cache {
+        Some(cache) => Box::new(CachedService::new(service, cache)),
+        None => service,
+    };
+    OverriddenService::new(service, overrides)
+}
+
+/// Identifiers `track` may be overridden by: its ISRC, then the ids of its
+/// Spotify, Apple Music, YouTube and Deezer records and the URL of its Bandcamp
+/// record
+fn source_keys(track: &Track) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::with_capacity(6);
+
+    if let Some(isrc) = &track.isrc {
+        keys.push(Overrides::isrc_key(isrc));
+    }
+    if let Some(spotify) = &track.services.spotify {
+        keys.push(Overrides::id_key("Spotify", &spotify.id));
+    }
+    if let Some(apple_music) = &track.services.apple_music {
+        keys.push(Overrides::id_key("Apple Music", &apple_music.id));
+    }
+    if let Some(youtube) = &track.services.youtube {
+        keys.push(Overrides::id_key("YouTube", &youtube.id));
+    }
+    if let Some(bandcamp) = &track.services.bandcamp {
+        keys.push(Overrides::id_key("Bandcamp", &bandcamp.url));
+    }
+    if let Some(deezer) = &track.services.deezer {
+        keys.push(Overrides::id_key("Deezer", &deezer.id));
+    }
+
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        apple_music::AppleMusicProvider,
+        config::ServiceConfig,
+        overrides::{OverriddenService, Override, Overrides},
+        replay::ReplayServer,
+        request::RequestPolicy,
+        service::{MatchMethod, Services, Source},
+        track::Track,
+        youtube::YouTubeProvider,
+    };
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn overrides_replace_searching() {
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "songvert-overrides_replace_searching-{}.json",
+            std::process::id()
+        ));
+        let mut overrides: Overrides = Overrides::open(&path).unwrap();
+        let key: String = Overrides::isrc_key("uszud1215001");
+        overrides.insert(&key, "Apple Music", Override::Id("1587311240".to_string()));
+        overrides.insert(&key, "YouTube", Override::Skip);
+        overrides.save().unwrap();
+        let overrides: Arc<Overrides> = Arc::new(Overrides::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let mut track: Track = Track {
+            name: "Duchess for Nothing".to_owned(),
+            album: "Genius Fatigue".to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_owned()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            services: Services::default(),
+            isrc: Some("USZUD1215001".to_owned()),
+            source_service: Source::Spotify,
+        };
+
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer = ReplayServer::start(
+            "apple_music/get_match_no_isrc",
+            ServiceConfig::apple_music(),
+        )
+        .await;
+        let apple_music = OverriddenService::new(
+            Box::new(AppleMusicProvider::with_config(
+                &server.auth("APPLE_MUSIC_TOKEN"),
+                server.config(),
+            )),
+            overrides.clone(),
+        );
+        // nothing is listening here, so searching would fail
+        let youtube = OverriddenService::new(
+            Box::new(YouTubeProvider::with_config(
+                ServiceConfig::youtube()
+                    .with_base_url("http://127.0.0.1:9")
+                    .with_request_policy(RequestPolicy {
+                        max_retries: 0,
+                        ..Default::default()
+                    }),
+            )),
+            overrides,
+        );
+
+        track.add_service(&client, &apple_music).await.unwrap();
+        track.add_service(&client, &youtube).await.unwrap();
+
+        let apple_music = track.services.apple_music.unwrap();
+        assert_eq!(apple_music.id, "1587311240");
+        assert_eq!(
+            apple_music.provenance.unwrap().method,
+            MatchMethod::DirectId
+        );
+        assert!(track.services.youtube.is_none());
+    }
+}
@@ -0,0 +1,389 @@
+//! Record/replay harness for the service tests.
+//!
+//! A `ReplayServer` is a set of local HTTP listeners, one per base URL of a
+//! `ServiceConfig` (API, website, token endpoint and media), that answer
+//! requests from a shared cassette in `tests/fixtures`. Services are pointed at
+//! them with `ReplayServer::config`, so the whole pipeline (requests, parsing
+//! and matching) runs offline.
+//!
+//! Running the tests with `SONGVERT_RECORD=1` forwards every request to the real
+//! host its listener stands in for and rewrites the cassette with what came
+//! back. Tokens for recording are read from the environment, see
+//! `ReplayServer::auth`.
+
+use crate::config::{rebase_url, ServiceConfig};
+use reqwest::header::RETRY_AFTER;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub const RECORD_ENV_VAR: &str = "SONGVERT_RECORD";
+
+/// One request and the response it got
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Interaction {
+    method: String,
+    /// Path and query, percent-encoding is ignored when matching
+    path: String,
+    /// When present, every field in here must match the JSON request body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_body: Option<Value>,
+    status: u16,
+    /// Response headers worth replaying, e.g. `retry-after`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+    response: Value,
+}
+
+struct Cassette {
+    path: PathBuf,
+    interactions: Vec<Interaction>,
+    /// Number of times each interaction was served
+    served: Vec<usize>,
+}
+
+/// Where a listener forwards requests when recording
+#[derive(Clone)]
+enum Upstream {
+    /// `scheme://host[:port]` of one of the upstream base URLs
+    Origin(String),
+    /// Media URLs, rewritten by `ServiceConfig::media_url` to start with their host
+    Media,
+}
+
+pub struct ReplayServer {
+    api_url: String,
+    site_url: String,
+    auth_url: String,
+    media_url: String,
+    upstream: ServiceConfig,
+    recording: bool,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl ReplayServer {
+    /// Serve `tests/fixtures/{name}.json` in place of `upstream`. When recording,
+    /// requests go to the host of the base URL they were sent to and the
+    /// cassette is rewritten.
+    pub async fn start(name: &str, upstream: ServiceConfig) -> Self {
+        let recording: bool = std::env::var_os(RECORD_ENV_VAR).is_some();
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures"]
+            .iter()
+            .collect::<PathBuf>()
+            .join(format!("{}.json", name));
+
+        let interactions: Vec<Interaction> = if recording {
+            Vec::new()
+        } else {
+            let raw: String = std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("unable to read {}: {}", path.to_string_lossy(), e));
+            serde_json::from_str(&raw)
+                .unwrap_or_else(|e| panic!("invalid cassette {}: {}", path.to_string_lossy(), e))
+        };
+
+        let cassette = Arc::new(Mutex::new(Cassette {
+            path,
+            served: vec![0; interactions.len()],
+            interactions,
+        }));
+
+        let origin_of = |url: &str| Upstream::Origin(origin(url).to_owned());
+        ReplayServer {
+            api_url: listen(&cassette, recording, origin_of(&upstream.api_base_url)).await,
+            site_url: listen(&cassette, recording, origin_of(&upstream.site_base_url)).await,
+            auth_url: listen(&cassette, recording, origin_of(&upstream.auth_base_url)).await,
+            media_url: listen(&cassette, recording, Upstream::Media).await,
+            upstream,
+            recording,
+        }
+    }
+
+    /// The upstream `ServiceConfig` pointed at this server
+    pub fn config(&self) -> ServiceConfig {
+        let mut config: ServiceConfig = self.upstream.clone();
+        config.api_base_url = rebase_url(&config.api_base_url, &self.api_url);
+        config.site_base_url = rebase_url(&config.site_base_url, &self.site_url);
+        config.auth_base_url = rebase_url(&config.auth_base_url, &self.auth_url);
+        config.media_base_url = Some(self.media_url.to_owned());
+        config
+    }
+
+    /// Token to send: `env_var` when recording, a placeholder when replaying
+    pub fn auth(&self, env_var: &str) -> String {
+        if self.recording {
+            std::env::var(env_var)
+                .unwrap_or_else(|_| panic!("{} must be set to record fixtures", env_var))
+        } else {
+            "replay".to_string()
+        }
+    }
+}
+
+/// Start a listener serving `cassette`, forwarding to `upstream` when recording.
+/// Returns its base URL.
+async fn listen(cassette: &Arc<Mutex<Cassette>>, recording: bool, upstream: Upstream) -> String {
+    let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url: String = format!("http://{}", listener.local_addr().unwrap());
+
+    let cassette: Arc<Mutex<Cassette>> = cassette.clone();
+    let upstream: Option<Upstream> = recording.then_some(upstream);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle_connection(
+                stream,
+                cassette.clone(),
+                upstream.clone(),
+            ));
+        }
+    });
+
+    base_url
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    cassette: Arc<Mutex<Cassette>>,
+    upstream: Option<Upstream>,
+) {
+    let request: Request = match read_request(&mut stream).await {
+        Some(request) => request,
+        None => return,
+    };
+
+    let (status, headers, body): (u16, BTreeMap<String, String>, String) = match upstream {
+        Some(upstream) => record(&request, &upstream, &cassette).await,
+        None => replay(&request, &cassette),
+    };
+
+    let mut response: String = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        if status < 400 { "OK" } else { "Error" },
+        body.len(),
+    );
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    response.push_str(&body);
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Serve the first matching interaction that hasn't been used yet, so a cassette
+/// can hold e.g. a 429 followed by a 200 for the same path. Once all of them are
+/// used, the last one keeps being served.
+fn replay(
+    request: &Request,
+    cassette: &Mutex<Cassette>,
+) -> (u16, BTreeMap<String, String>, String) {
+    let request_body: Option<Value> = serde_json::from_slice(&request.body).ok();
+    let mut cassette = cassette.lock().unwrap();
+
+    let matches: Vec<usize> = cassette
+        .interactions
+        .iter()
+        .enumerate()
+        .filter(|(_, interaction)| {
+            interaction.method == request.method
+                && percent_decode(&interaction.path) == percent_decode(&request.path)
+                && match (&interaction.request_body, &request_body) {
+                    (None, _) => true,
+                    (Some(expected), Some(actual)) => is_subset(expected, actual),
+                    (Some(..), None) => false,
+                }
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    let i: usize = match matches
+        .iter()
+        .find(|i| cassette.served[**i] == 0)
+        .or(matches.last())
+    {
+        Some(i) => *i,
+        None => {
+            eprintln!(
+                "no interaction in {} for {} {}",
+                cassette.path.to_string_lossy(),
+                request.method,
+                percent_decode(&request.path)
+            );
+            return (
+                404,
+                BTreeMap::new(),
+                r#"{"error":"no recorded interaction"}"#.to_string(),
+            );
+        }
+    };
+
+    cassette.served[i] += 1;
+    let interaction: &Interaction = &cassette.interactions[i];
+    let body: String = match &interaction.response {
+        // non-JSON bodies (e.g. HTML) are recorded as a string
+        Value::String(body) => body.to_owned(),
+        response => response.to_string(),
+    };
+    (interaction.status, interaction.headers.clone(), body)
+}
+
+async fn record(
+    request: &Request,
+    upstream: &Upstream,
+    cassette: &Mutex<Cassette>,
+) -> (u16, BTreeMap<String, String>, String) {
+    let url: String = match upstream {
+        Upstream::Origin(origin) => format!("{}{}", origin, request.path),
+        Upstream::Media => format!("https:/{}", request.path),
+    };
+
+    let client = reqwest::Client::new();
+    let method: Method = Method::from_bytes(request.method.as_bytes()).unwrap();
+    let mut upstream_request = client.request(method, url);
+    for (name, value) in &request.headers {
+        if ["authorization", "origin", "content-type", "user-agent"].contains(&name.as_str()) {
+            upstream_request = upstream_request.header(name, value);
+        }
+    }
+
+    let mut headers: BTreeMap<String, String> = BTreeMap::new();
+    let (status, body): (u16, String) =
+        match upstream_request.body(request.body.to_owned()).send().await {
+            Ok(response) => {
+                if let Some(retry_after) = response.headers().get(RETRY_AFTER) {
+                    headers.insert(
+                        RETRY_AFTER.to_string(),
+                        retry_after.to_str().unwrap_or_default().to_owned(),
+                    );
+                }
+                (
+                    response.status().as_u16(),
+                    response.text().await.unwrap_or_default(),
+                )
+            }
+            Err(e) => (502, format!(r#"{{"error":"{}"}}"#, e)),
+        };
+
+    let mut cassette = cassette.lock().unwrap();
+    cassette.interactions.push(Interaction {
+        method: request.method.to_owned(),
+        path: request.path.to_owned(),
+        request_body: serde_json::from_slice(&request.body).ok(),
+        status,
+        headers: headers.clone(),
+        response: serde_json::from_str(&body).unwrap_or(Value::String(body.to_owned())),
+    });
+    cassette.served.push(1);
+    if let Some(parent) = cassette.path.parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+    std::fs::write(
+        &cassette.path,
+        serde_json::to_string_pretty(&cassette.interactions).unwrap(),
+    )
+    .unwrap();
+
+    (status, headers, body)
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end: usize = loop {
+        if let Some(i) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i;
+        }
+        let n: usize = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+
+    let head: String = String::from_utf8_lossy(&buffer[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method: String = request_line.next()?.to_owned();
+    let path: String = request_line.next()?.to_owned();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_owned()))
+        .collect();
+
+    let content_length: usize = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+
+    let mut body: Vec<u8> = buffer[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n: usize = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+/// `expected` with every one of its fields equal in `actual`
+fn is_subset(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => expected.iter().all(|(key, value)| {
+            actual
+                .get(key)
+                .is_some_and(|actual_value| is_subset(value, actual_value))
+        }),
+        _ => expected == actual,
+    }
+}
+
+fn percent_decode(raw: &str) -> String {
+    let bytes: &[u8] = raw.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i: usize = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// `scheme://host[:port]` of `url`
+fn origin(url: &str) -> &str {
+    let start: usize = url.find("://").map_or(0, |i| i + 3);
+    match url[start..].find('/') {
+        Some(i) => &url[..start + i],
+        None => url,
+    }
+}
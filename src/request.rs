@@ -0,0 +1,309 @@
+use crate::error::{Error, Result};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::Instant;
+
+/// How requests to a single service are paced and retried
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestPolicy {
+    /// Requests allowed in flight at once
+    pub max_concurrent_requests: usize,
+    /// Requests started per second, `None` for no limit
+    pub requests_per_second: Option<f64>,
+    /// Retries after a 429, a 5xx or a connection error
+    pub max_retries: u32,
+    /// Backoff before the first retry, doubled for every retry after it
+    pub initial_backoff: Duration,
+    /// Upper bound on the backoff between two attempts
+    pub max_backoff: Duration,
+}
+
+/// Sends requests following a `RequestPolicy`.
+///
+/// Clones share the same concurrency and rate budget, so every provider built
+/// from one `ServiceConfig` is limited together.
+#[derive(Debug, Clone)]
+pub struct RequestExecutor {
+    policy: RequestPolicy,
+    permits: Arc<Semaphore>,
+    next_slot: Arc<Mutex<Instant>>,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        RequestPolicy {
+            max_concurrent_requests: 8,
+            requests_per_second: None,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RequestExecutor {
+    pub fn new(policy: RequestPolicy) -> Self {
+        RequestExecutor {
+            permits: Arc::new(Semaphore::new(policy.max_concurrent_requests.max(1))),
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+            policy,
+        }
+    }
+
+    pub fn policy(&self) -> &RequestPolicy {
+        &self.policy
+    }
+
+    /// Send `request`, waiting for a free slot first and retrying rate limits
+    /// (honouring `Retry-After` up to `max_backoff`), server errors and
+    /// connection errors with exponential backoff. The slot is given up while
+    /// waiting to retry, and a longer `Retry-After` is returned as
+    /// `Error::RateLimited` straight away. Once out of retries a 429 is returned as
+    /// `Error::RateLimited`, a 401 as `Error::Unauthorized`, a 404 as
+    /// `Error::NotFound` and any other error status straight away.
+    pub async fn send(&self, mut request: RequestBuilder) -> Result<Response> {
+        let mut attempt: u32 = 0;
+        loop {
+            let permit = self
+                .permits
+                .acquire()
+                .await
+                .expect("request semaphore is never closed");
+            self.wait_for_rate_limit().await;
+
+            // `None` once out of retries, or for bodies that can't be cloned
+            let retry: Option<RequestBuilder> = match attempt < self.policy.max_retries {
+                true => request.try_clone(),
+                false => None,
+            };
+
+            let delay: Duration = match request.send().await {
+                Ok(response) if retry.is_some() && is_retryable(response.status()) => {
+                    let delay: Duration = match retry_after(&response) {
+                        Some(retry_after) if retry_after > self.policy.max_backoff => {
+                            return Err(Error::RateLimited {
+                                retry_after: Some(retry_after),
+                            })
+                        }
+                        Some(retry_after) => retry_after,
+                        None => self.backoff(attempt),
+                    };
+                    log::warn!(
+                        "\t{} from {}, retrying in {:.1}s",
+                        response.status(),
+                        response.url().path(),
+                        delay.as_secs_f64()
+                    );
+                    delay
+                }
+                Ok(response) if response.status() == StatusCode::UNAUTHORIZED => {
+                    return Err(Error::Unauthorized(format!(
+                        "{} from {}",
+                        response.status(),
+                        response.url().path()
+                    )))
+                }
+                Ok(response) if response.status() == StatusCode::NOT_FOUND => {
+                    return Err(Error::NotFound {
+                        service: response.url().host_str().unwrap_or_default().to_owned(),
+                        query: response.url().path().to_owned(),
+                    })
+                }
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    return Err(Error::RateLimited {
+                        retry_after: retry_after(&response),
+                    })
+                }
+                Ok(response) => return Ok(response.error_for_status()?),
+                Err(e) if retry.is_some() && (e.is_connect() || e.is_timeout()) => {
+                    let delay: Duration = self.backoff(attempt);
+                    log::warn!("\t{}, retrying in {:.1}s", e, delay.as_secs_f64());
+                    delay
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            // let other requests use the slot while this one waits
+            drop(permit);
+            tokio::time::sleep(delay).await;
+            request = retry.expect("only retried when the request could be cloned");
+            attempt += 1;
+        }
+    }
+
+    /// Wait until starting another request stays within `requests_per_second`
+    async fn wait_for_rate_limit(&self) {
+        let requests_per_second: f64 = match self.policy.requests_per_second {
+            Some(requests_per_second) if requests_per_second > 0.0 => requests_per_second,
+            _ => return,
+        };
+
+        let mut next_slot = self.next_slot.lock().await;
+        let now: Instant = Instant::now();
+        if *next_slot > now {
+            tokio::time::sleep_until(*next_slot).await;
+        }
+        *next_slot = (*next_slot).max(now) + Duration::from_secs_f64(1.0 / requests_per_second);
+    }
+
+    /// Exponential backoff for `attempt` with up to half of it as random jitter
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff: Duration = self
+            .policy
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.policy.max_backoff);
+        let nanos: u32 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        backoff / 2 + (backoff / 2).mul_f64(f64::from(nanos % 1000) / 1000.0)
+    }
+}
+
+impl Default for RequestExecutor {
+    fn default() -> Self {
+        Self::new(RequestPolicy::default())
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// `Retry-After` in seconds; HTTP dates are left to the regular backoff
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        config::ServiceConfig, error::Error, replay::ReplayServer, request::RequestPolicy,
+    };
+    use reqwest::Method;
+    use std::time::Duration;
+
+    const TRACK_PATH: &str = "tracks/6Jd9HwGbIUVjQpHwQ0R9yL";
+
+    #[tokio::test]
+    async fn retries_after_rate_limit() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("request/retry_after", ServiceConfig::spotify()).await;
+        let config: ServiceConfig = server.config();
+
+        let response = config
+            .send(config.request(&client, Method::GET, TRACK_PATH))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("request/server_error", ServiceConfig::spotify()).await;
+        let config: ServiceConfig = server.config().with_request_policy(RequestPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+
+        let result = config
+            .send(config.request(&client, Method::GET, TRACK_PATH))
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn reports_rate_limit_after_max_retries() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("request/rate_limited", ServiceConfig::spotify()).await;
+        let config: ServiceConfig = server.config().with_request_policy(RequestPolicy {
+            max_retries: 1,
+            ..Default::default()
+        });
+
+        let result = config
+            .send(config.request(&client, Method::GET, TRACK_PATH))
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::RateLimited {
+                retry_after: Some(retry_after)
+            }) if retry_after.is_zero()
+        ));
+    }
+
+    #[tokio::test]
+    async fn does_not_wait_longer_than_max_backoff() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("request/long_retry_after", ServiceConfig::spotify()).await;
+        let config: ServiceConfig = server.config();
+
+        let result = config
+            .send(config.request(&client, Method::GET, TRACK_PATH))
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::RateLimited {
+                retry_after: Some(retry_after)
+            }) if retry_after == Duration::from_secs(86400)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_missing_resource_as_not_found() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("request/not_found", ServiceConfig::spotify()).await;
+        let config: ServiceConfig = server.config();
+
+        let result = config
+            .send(config.request(&client, Method::GET, TRACK_PATH))
+            .await;
+
+        assert!(matches!(result, Err(Error::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn limits_requests_per_second() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("request/retry_after", ServiceConfig::spotify()).await;
+        let config: ServiceConfig = server.config().with_request_policy(RequestPolicy {
+            requests_per_second: Some(20.0),
+            ..Default::default()
+        });
+
+        let start = tokio::time::Instant::now();
+        for _ in 0..3 {
+            let _ = config
+                .send(config.request(&client, Method::GET, TRACK_PATH))
+                .await;
+        }
+
+        // four requests (one retried) need at least three 50ms gaps
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+}
@@ -1,21 +1,42 @@
+use crate::album;
 use crate::apple_music::AppleMusic;
 use crate::bandcamp::Bandcamp;
+use crate::deezer::Deezer;
+use crate::error::{Error, Result};
+use crate::matcher::MatchReport;
+use crate::playlist::Playlist;
 use crate::spotify::Spotify;
+use crate::track::Track;
 use crate::youtube::YouTube;
+use async_trait::async_trait;
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default)]
 pub struct Services {
     pub spotify: Option<Spotify>,
     pub apple_music: Option<AppleMusic>,
     pub youtube: Option<YouTube>,
     pub bandcamp: Option<Bandcamp>,
+    pub deezer: Option<Deezer>,
+    /// Records added by `MusicService` implementations outside this crate,
+    /// keyed by `MusicService::name`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub other: BTreeMap<String, Value>,
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub enum Source {
     Spotify,
     AppleMusic,
+    YouTube,
+    Bandcamp,
+    Deezer,
+    Other(String),
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
@@ -34,3 +55,187 @@ pub struct Album {
     pub ean: Option<String>,
     pub upc: Option<String>,
 }
+
+/// How a service record was matched to its track
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMethod {
+    /// Looked up by its id, e.g. the track a conversion started from
+    DirectId,
+    /// Looked up by the track's ISRC
+    Isrc,
+    /// Picked from the album with the UPC of the track's album
+    Upc,
+    /// Found by searching for the track's metadata
+    FuzzySearch,
+}
+
+/// How and when a service record was found, so consumers of saved JSON can
+/// filter low-confidence matches and verify them again
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct MatchProvenance {
+    pub method: MatchMethod,
+    /// `MatchReport::score` of the record, when it was scored
+    pub score: Option<f64>,
+    /// Id, ISRC or search query the record was found with
+    pub query: Option<String>,
+    /// Seconds since the Unix epoch
+    pub matched_at: u64,
+    /// Only the clean version of an explicit track (or the other way around,
+    /// see `ExplicitPreference`) was found
+    #[serde(default)]
+    pub other_version: bool,
+}
+
+impl MatchProvenance {
+    pub fn new(method: MatchMethod, score: Option<f64>, query: &str) -> Self {
+        MatchProvenance {
+            method,
+            score,
+            query: Some(query.to_owned()),
+            matched_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            other_version: false,
+        }
+    }
+
+    /// Provenance of the candidate in `report`
+    pub fn from_report(method: MatchMethod, report: &MatchReport, query: &str) -> Self {
+        MatchProvenance {
+            other_version: report.is_other_version(),
+            ..Self::new(method, Some(report.score), query)
+        }
+    }
+
+    /// Provenance of a record looked up by its `id`
+    pub fn direct_id(id: &str) -> Self {
+        Self::new(MatchMethod::DirectId, None, id)
+    }
+}
+
+/// Common interface for every streaming service songvert can convert
+/// to or from.
+///
+/// Implementors hold whatever credentials their API needs, so a
+/// `Vec<Box<dyn MusicService>>` can be iterated over by `Track`,
+/// `Playlist`, `Album` and the CLI without caring which service is which.
+#[async_trait]
+pub trait MusicService: Send + Sync {
+    /// Human readable name used in log output and as the `Services::other` key
+    fn name(&self) -> &str;
+
+    /// `Source` assigned to tracks created by this service
+    fn source(&self) -> Source;
+
+    /// Country catalog searched, e.g. `us`, for services that have one
+    fn storefront(&self) -> Option<&str> {
+        None
+    }
+
+    /// Look up a track in the service's catalog and build a full `Track` from it
+    async fn create_track_from_id(&self, _client: &Client, _id: &str) -> Result<Track> {
+        Err(Error::TrackError(format!(
+            "{} cannot be used as a source",
+            self.name()
+        )))
+    }
+
+    /// Look up a playlist by its id and build a `Playlist` of full tracks from it
+    async fn create_playlist_from_id(&self, _client: &Client, _id: &str) -> Result<Playlist> {
+        Err(Error::TrackError(format!(
+            "{} does not support playlists as a source",
+            self.name()
+        )))
+    }
+
+    /// Look up an album by its id and build an `Album` of full tracks from it
+    async fn create_album_from_id(&self, _client: &Client, _id: &str) -> Result<album::Album> {
+        Err(Error::TrackError(format!(
+            "{} does not support albums as a source",
+            self.name()
+        )))
+    }
+
+    /// This service's record in `track.services`, if it has one
+    fn service_record(&self, track: &Track) -> Option<Value> {
+        track.services.other.get(self.name()).cloned()
+    }
+
+    /// Store a record previously returned by `service_record` in `track.services`
+    fn set_service_record(&self, track: &mut Track, record: Value) -> Result<()> {
+        track.services.other.insert(self.name().to_owned(), record);
+        Ok(())
+    }
+
+    /// Search the service's catalog for `track`'s metadata and score every result
+    /// against it, best match first. Unlike `create_service_for_track` this never
+    /// looks up the ISRC, so it shows the alternatives when a match is wrong.
+    async fn search(&self, _client: &Client, _track: &Track) -> Result<Vec<MatchReport>> {
+        Err(Error::TrackError(format!(
+            "{} does not support searching",
+            self.name()
+        )))
+    }
+
+    /// Fetch the record with `id` and store it in `track.services` without
+    /// searching, e.g. for a forced match from `overrides`
+    async fn create_service_from_id(
+        &self,
+        _client: &Client,
+        _track: &mut Track,
+        _id: &str,
+    ) -> Result<()> {
+        Err(Error::TrackError(format!(
+            "{} cannot look up tracks by id",
+            self.name()
+        )))
+    }
+
+    /// Search the service for `track` and store the best matching record in
+    /// `track.services`
+    async fn create_service_for_track(&self, client: &Client, track: &mut Track) -> Result<()>;
+
+    /// Add this service to many tracks at once. Failures for individual tracks are
+    /// logged rather than returned. Services with batch endpoints should override this.
+    async fn create_services_for_tracks(
+        &self,
+        client: &Client,
+        tracks: &mut [Track],
+    ) -> Result<()> {
+        let mut service_futures = Vec::with_capacity(tracks.len());
+        for track in tracks.iter_mut() {
+            service_futures.push(self.create_service_for_track(client, track));
+        }
+        let results = futures::future::join_all(service_futures).await;
+
+        for (i, result) in results.into_iter().enumerate() {
+            if let Err(e) = result {
+                log::warn!(
+                    "\tSkipping adding {} to track ({}) {}: {}",
+                    self.name(),
+                    i + 1,
+                    tracks[i].name,
+                    e
+                )
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Download the audio for `track` from this service, returning the path written.
+    /// The file extension tells the caller which kind of metadata to add.
+    async fn download(
+        &self,
+        _client: &Client,
+        _track: &Track,
+        _path: &Path,
+        _filename: &str,
+    ) -> Result<PathBuf> {
+        Err(Error::DownloadError(format!(
+            "{} does not support downloading",
+            self.name()
+        )))
+    }
+}
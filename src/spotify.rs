@@ -1,10 +1,19 @@
+use crate::album;
+use crate::auth::{authorized, CredentialProvider, StaticToken};
+use crate::config::ServiceConfig;
 use crate::error::{Error, Result};
+use crate::matcher::{Candidate, MatchReport, Matcher};
+use crate::normalize::{search_text, search_title};
 use crate::playlist::Playlist;
-use crate::service::{Album, Artist, Services, Source};
+use crate::service::{Album, Artist, MatchMethod, MatchProvenance, MusicService, Services, Source};
 use crate::track::Track;
-use reqwest::{Client, RequestBuilder, Response};
+use crate::utils::{parse_release_date, parse_response};
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::{Client, Method, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::sync::Arc;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct Spotify {
@@ -16,6 +25,14 @@ pub struct Spotify {
     pub duration_ms: usize,
     pub image: Option<String>,
     pub audio_preview: Option<String>,
+    /// How this record was matched to its track
+    pub provenance: Option<MatchProvenance>,
+}
+
+/// `MusicService` implementation for the Spotify Web API
+pub struct SpotifyProvider {
+    pub credentials: Arc<dyn CredentialProvider>,
+    pub config: ServiceConfig,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -59,11 +76,12 @@ struct RawPlaylistTracks {
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 struct RawPlaylistTrackItem {
-    added_at: String,
+    added_at: Option<String>,
     added_by: Value,
     is_local: bool,
     primary_color: Option<String>,
-    track: RawTrack,
+    // null for removed items, and may be a podcast episode instead of a track
+    track: Option<Value>,
     // video_thumbnail: Value,
 }
 
@@ -105,6 +123,51 @@ struct RawTrackAlbum {
     total_tracks: usize,
 }
 
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawAlbum {
+    album_type: String,
+    artists: Vec<RawTrackArtist>,
+    external_ids: RawAlbumExternalIds,
+    external_urls: RawExternalUrls,
+    href: String,
+    id: String,
+    images: Vec<RawImage>,
+    label: Option<String>,
+    name: String,
+    release_date: String,
+    release_date_precision: String,
+    total_tracks: usize,
+    tracks: RawAlbumTracks,
+    r#type: String,
+    uri: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawAlbumTracks {
+    href: String,
+    items: Vec<RawAlbumTrackItem>,
+    limit: usize,
+    next: Option<String>,
+    offset: usize,
+    previous: Option<String>,
+    total: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawAlbumTrackItem {
+    id: String,
+    name: String,
+    disc_number: usize,
+    track_number: usize,
+    is_local: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct RawAlbumExternalIds {
+    upc: Option<String>,
+    ean: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 struct RawTrackArtist {
     external_urls: RawExternalUrls,
@@ -135,11 +198,15 @@ struct RawExternalUrls {
 impl Spotify {
     pub const API_BASE_URL: &'static str = "https://api.spotify.com/v1";
     pub const SITE_BASE_URL: &'static str = "https://open.spotify.com";
+    pub const ACCOUNTS_BASE_URL: &'static str = "https://accounts.spotify.com";
 
-    pub async fn get_public_session_info(client: &Client) -> Result<SessionInfo> {
-        let request: RequestBuilder = client.get(Self::SITE_BASE_URL);
-        let mut response: Response = request.send().await?;
-        response = response.error_for_status()?;
+    pub async fn get_public_session_info(
+        client: &Client,
+        config: &ServiceConfig,
+    ) -> Result<SessionInfo> {
+        let request: RequestBuilder =
+            config.request_url(client, Method::GET, &config.site_base_url);
+        let response: Response = config.send(request).await?;
 
         let raw_html: String = response.text().await?;
         let re = regex::Regex::new(r#"(\{"accessToken":.*"\})"#)?;
@@ -151,126 +218,380 @@ impl Spotify {
             }
         }
 
-        Err(Error::DatabaseError(
-            "unable to grab Spotify Session Info".to_string(),
+        Err(Error::unexpected_response(
+            "Spotify",
+            &config.site_base_url,
+            &raw_html,
         ))
     }
 
-    async fn get(client: &Client, auth: &str, path: &str) -> Result<Value> {
-        let request: RequestBuilder = client
-            .get(format!("{}/{}", Self::API_BASE_URL, path))
-            .header("Authorization", format!("Bearer {}", auth));
-        let mut response: Response = request.send().await?;
-        response = response.error_for_status()?;
+    async fn get(
+        client: &Client,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
+        path: &str,
+    ) -> Result<Value> {
+        let response: Response = authorized(client, auth, |token| {
+            config.send(
+                config
+                    .request(client, Method::GET, path)
+                    .header("Authorization", format!("Bearer {}", token)),
+            )
+        })
+        .await?;
 
-        let data: Value = serde_json::from_str(&response.text().await?)?;
+        let body: String = response.text().await?;
+        let data: Value = serde_json::from_str(&body)
+            .map_err(|_| Error::unexpected_response("Spotify", path, &body))?;
 
         Ok(data)
     }
 
+    fn candidate_from_raw(raw_track: &RawTrack) -> Candidate {
+        Candidate {
+            id: raw_track.id.to_owned(),
+            url: raw_track.external_urls.spotify.to_owned(),
+            name: raw_track.name.to_owned(),
+            artists: raw_track
+                .artists
+                .iter()
+                .map(|artist| artist.name.to_owned())
+                .collect(),
+            album: Some(raw_track.album.name.to_owned()),
+            duration_ms: Some(raw_track.duration_ms),
+            isrc: raw_track.external_ids.isrc.to_owned(),
+            is_explicit: Some(raw_track.explicit),
+            release_year: parse_release_date(&raw_track.album.release_date)
+                .ok()
+                .map(|(year, _, _)| year),
+        }
+    }
+
+    /// Tracks found by a `search?type=track` query, ranked against `track`
+    async fn get_ranked_raw_tracks(
+        client: &Client,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
+        track: &Track,
+        query: &str,
+    ) -> Result<Vec<(MatchReport, RawTrack)>> {
+        let mut raw_result: Value = Self::get(
+            client,
+            config,
+            auth,
+            &format!(
+                "search?type=track&market={}&q={}",
+                config.storefront.to_uppercase(),
+                query
+            ),
+        )
+        .await?;
+
+        let raw_tracks: Vec<RawTrack> = match raw_result["tracks"]["items"].take() {
+            Value::Array(items) => items
+                .into_iter()
+                .filter_map(|item| serde_json::from_value(item).ok())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(config.matcher.rank(
+            track,
+            raw_tracks
+                .into_iter()
+                .map(|raw_track| (Self::candidate_from_raw(&raw_track), raw_track))
+                .collect(),
+        ))
+    }
+
+    /// Query searching for `track`'s metadata
+    fn search_query(track: &Track) -> String {
+        format!(
+            "track:{}%20artist:{}%20album:{}%20year:{}",
+            search_title(&track.name),
+            track
+                .artists
+                .iter()
+                .map(|artist| search_text(artist))
+                .collect::<Vec<String>>()
+                .join("+"),
+            search_title(&track.album),
+            track.release_year
+        )
+        .replace(" ", "+")
+    }
+
     async fn get_raw_track_match_from_track(
         client: &Client,
-        auth: &str,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
         track: &Track,
-    ) -> Result<RawTrack> {
-        match &track.isrc {
-            Some(isrc) => {
-                match Self::get(
-                    client,
-                    auth,
-                    &format!("search?type=track&q=isrc:{}%20album:{}", isrc, &track.album),
-                )
-                .await
-                {
-                    Ok(mut raw_result) => {
-                        if let Some(items) = raw_result["tracks"]["items"].as_array_mut() {
-                            if items.len() > 0 {
-                                return Ok(serde_json::from_value(items[0].take())?);
-                            }
-                        }
-                    }
-                    Err(..) => (),
-                }
+    ) -> Result<(RawTrack, MatchProvenance)> {
+        if let Some(isrc) = config.matcher.wanted_isrc(track) {
+            let query: String = format!("isrc:{}%20album:{}", isrc, search_title(&track.album));
+            // only a missing ISRC falls back to searching metadata, rate limits
+            // and rejected tokens would fail that search too
+            let ranked: Vec<(MatchReport, RawTrack)> =
+                match Self::get_ranked_raw_tracks(client, config, auth, track, &query).await {
+                    Ok(ranked) => ranked,
+                    Err(Error::NotFound { .. }) => Vec::new(),
+                    Err(e) => return Err(e),
+                };
+            // the ISRC already identifies the recording, ranking just picks the
+            // best release of it
+            if let Some((report, raw_track)) = ranked.into_iter().next() {
+                let provenance = MatchProvenance::from_report(MatchMethod::Isrc, &report, &query);
+                return Ok((raw_track, provenance));
             }
-            None => (),
         }
-        // no isrc or isrc search failed
+        // no isrc or nothing found by it
 
-        Ok(serde_json::from_value::<RawTrack>(
-            Self::get(
-                client,
-                auth,
-                &format!(
-                    "search?type=track&q=track:{}%20artist:{}%20album:{}%20year:{}",
-                    track.name,
-                    track.artists.join("+"),
-                    track.album,
-                    track.release_year
-                )
-                .replace(" ", "+"),
-            )
-            .await?["tracks"]["items"]
-                .get_mut(0)
-                .ok_or(Error::TrackError("no match found".to_string()))?
-                .take(),
-        )?)
+        let query: String = Self::search_query(track);
+        Matcher::best_match(Self::get_ranked_raw_tracks(client, config, auth, track, &query).await?)
+            .map(|(report, raw_track)| {
+                let provenance =
+                    MatchProvenance::from_report(MatchMethod::FuzzySearch, &report, &query);
+                (raw_track, provenance)
+            })
+            .ok_or(Error::NotFound {
+                service: "Spotify".to_string(),
+                query: format!("{} - {}", track.name, track.artists.join(", ")),
+            })
+    }
+
+    /// Every track found by searching for `track`'s metadata, best match first
+    pub async fn search(
+        client: &Client,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
+        track: &Track,
+    ) -> Result<Vec<MatchReport>> {
+        Ok(
+            Self::get_ranked_raw_tracks(client, config, auth, track, &Self::search_query(track))
+                .await?
+                .into_iter()
+                .map(|(report, _)| report)
+                .collect(),
+        )
     }
 
     pub async fn create_service_for_track(
         client: &Client,
-        auth: &str,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
         track: &mut Track,
     ) -> Result<()> {
-        let data: RawTrack = Self::get_raw_track_match_from_track(client, auth, track).await?;
-        let service: Self = Self::create_service_from_raw(&data).await?;
+        let search_track: &Track = track;
+        let (data, provenance): (RawTrack, MatchProvenance) = config
+            .find_in_storefronts(|config| async move {
+                Self::get_raw_track_match_from_track(client, &config, auth, search_track).await
+            })
+            .await?;
+        let service: Self = Self::create_service_from_raw(&data, provenance).await?;
         track.services.spotify = Some(service);
         Ok(())
     }
 
     pub async fn create_track_from_id(
         client: &Client,
-        auth: &str,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
         track_id: &str,
     ) -> Result<Track> {
-        match serde_json::from_value(
-            Self::get(client, auth, &format!("tracks/{}", track_id)).await?,
-        ) {
-            Ok(raw_track) => Self::create_track_from_raw(&raw_track).await,
-            Err(..) => Err(Error::TrackError(format!(
-                "unable to create track from id: {}",
-                track_id
-            ))),
+        let path: String = format!("tracks/{}", track_id);
+        let raw_track: RawTrack = parse_response(
+            "Spotify",
+            &path,
+            &Self::get(client, config, auth, &path).await?,
+        )?;
+        Self::create_track_from_raw(&raw_track).await
+    }
+
+    /// Fetch many tracks using the several-tracks endpoint, 50 ids per request.
+    /// Tracks are returned in the order of `track_ids`; unknown ids and tracks
+    /// that can't be parsed are skipped.
+    pub async fn create_tracks_from_ids(
+        client: &Client,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
+        track_ids: &[&str],
+    ) -> Result<Vec<Track>> {
+        const MAX_IDS_PER_REQUEST: usize = 50;
+
+        let mut page_futures = Vec::new();
+        for chunk in track_ids.chunks(MAX_IDS_PER_REQUEST) {
+            let path = format!("tracks?ids={}", chunk.join(","));
+            page_futures.push(async move { Self::get(client, config, auth, &path).await });
         }
+
+        let pages: Vec<Result<Value>> = futures::stream::iter(page_futures)
+            .buffered(config.executor.policy().max_concurrent_requests)
+            .collect()
+            .await;
+
+        let mut tracks: Vec<Track> = Vec::with_capacity(track_ids.len());
+        for page in pages {
+            let raw_tracks: Vec<Option<Value>> = serde_json::from_value(page?["tracks"].take())?;
+            for raw_track in raw_tracks.into_iter().flatten() {
+                let track: Result<Track> = match serde_json::from_value(raw_track) {
+                    Ok(raw_track) => Self::create_track_from_raw(&raw_track).await,
+                    Err(e) => Err(e.into()),
+                };
+                match track {
+                    Ok(track) => tracks.push(track),
+                    Err(e) => log::warn!("\tSkipping track: {}", e),
+                }
+            }
+        }
+
+        Ok(tracks)
+    }
+
+    /// Add Spotify to many tracks, running at most `max_concurrent_requests`
+    /// searches at once. Per-track failures are logged, not returned.
+    pub async fn create_services_for_tracks(
+        client: &Client,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
+        tracks: &mut [Track],
+    ) -> Result<()> {
+        let mut service_futures = Vec::with_capacity(tracks.len());
+        for (i, track) in tracks.iter_mut().enumerate() {
+            service_futures.push(async move {
+                (
+                    i,
+                    Self::create_service_for_track(client, config, auth, track).await,
+                )
+            });
+        }
+
+        let results: Vec<(usize, Result<()>)> = futures::stream::iter(service_futures)
+            .buffer_unordered(config.executor.policy().max_concurrent_requests)
+            .collect()
+            .await;
+
+        for (i, result) in results {
+            if let Err(e) = result {
+                log::warn!(
+                    "\tSkipping adding Spotify to track ({}) {}: {}",
+                    i + 1,
+                    tracks[i].name,
+                    e
+                )
+            }
+        }
+
+        Ok(())
     }
 
     pub async fn create_playlist_from_id(
         client: &Client,
-        auth: &str,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
         playlist_id: &str,
     ) -> Result<Playlist> {
-        let raw_playlist: RawPlaylist = match serde_json::from_value(
-            Self::get(client, auth, &format!("playlists/{}", playlist_id)).await?,
-        ) {
-            Ok(p) => p,
-            Err(..) => {
-                return Err(Error::TrackError(format!(
-                    "unable to create playlist from id: {}",
-                    playlist_id
-                )))
-            }
-        };
+        let path: String = format!("playlists/{}", playlist_id);
+        let mut raw_playlist: RawPlaylist = parse_response(
+            "Spotify",
+            &path,
+            &Self::get(client, config, auth, &path).await?,
+        )?;
 
-        if raw_playlist.tracks.next != None {
-            log::error!(
-                "There are more than 100 items in the playlist. Pagination needs to be implemented ASAP!"
-            );
-            todo!();
+        if raw_playlist.tracks.next.is_some() {
+            Self::get_remaining_raw_playlist_tracks(
+                client,
+                config,
+                auth,
+                playlist_id,
+                &mut raw_playlist.tracks,
+            )
+            .await?;
         }
 
         Self::create_playlist_from_raw(&raw_playlist).await
     }
 
-    async fn create_service_from_raw(raw_track: &RawTrack) -> Result<Self> {
+    /// Fill `raw_playlist_tracks` with every page after the first, fetching
+    /// up to `MAX_CONCURRENT_PAGE_REQUESTS` pages at a time
+    async fn get_remaining_raw_playlist_tracks(
+        client: &Client,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
+        playlist_id: &str,
+        raw_playlist_tracks: &mut RawPlaylistTracks,
+    ) -> Result<()> {
+        const MAX_CONCURRENT_PAGE_REQUESTS: usize = 4;
+
+        let limit: usize = raw_playlist_tracks.limit.max(1);
+        let offsets: Vec<usize> = (raw_playlist_tracks.offset + raw_playlist_tracks.items.len()
+            ..raw_playlist_tracks.total)
+            .step_by(limit)
+            .collect();
+
+        let pages: Vec<Result<Value>> = futures::stream::iter(offsets)
+            .map(|offset| async move {
+                let path = format!(
+                    "playlists/{}/tracks?offset={}&limit={}",
+                    playlist_id, offset, limit
+                );
+                Self::get(client, config, auth, &path).await
+            })
+            .buffered(MAX_CONCURRENT_PAGE_REQUESTS)
+            .collect()
+            .await;
+
+        let mut next: Option<String> = raw_playlist_tracks.next.take();
+        for page in pages {
+            let mut page: RawPlaylistTracks = serde_json::from_value(page?)?;
+            raw_playlist_tracks.items.append(&mut page.items);
+            next = page.next;
+        }
+
+        // the playlist grew while it was being fetched
+        while let Some(next_url) = next {
+            let mut page: RawPlaylistTracks = serde_json::from_value(
+                Self::get(client, config, auth, config.api_path(&next_url)).await?,
+            )?;
+            if page.items.is_empty() {
+                break;
+            }
+            raw_playlist_tracks.items.append(&mut page.items);
+            next = page.next;
+        }
+
+        Ok(())
+    }
+
+    pub async fn create_album_from_id(
+        client: &Client,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
+        album_id: &str,
+    ) -> Result<album::Album> {
+        let path: String = format!("albums/{}", album_id);
+        let mut raw_album: RawAlbum = parse_response(
+            "Spotify",
+            &path,
+            &Self::get(client, config, auth, &path).await?,
+        )?;
+
+        // the album endpoint only returns the first 50 tracks
+        let mut next: Option<String> = raw_album.tracks.next.take();
+        while let Some(next_url) = next {
+            let mut raw_album_tracks: RawAlbumTracks = serde_json::from_value(
+                Self::get(client, config, auth, config.api_path(&next_url)).await?,
+            )?;
+            raw_album.tracks.items.append(&mut raw_album_tracks.items);
+            next = raw_album_tracks.next;
+        }
+
+        Self::create_album_from_raw(client, config, auth, &raw_album).await
+    }
+
+    async fn create_service_from_raw(
+        raw_track: &RawTrack,
+        provenance: MatchProvenance,
+    ) -> Result<Self> {
         let mut artists: Vec<Artist> = Vec::new();
         for artist in &raw_track.artists {
             artists.push(Artist {
@@ -294,14 +615,13 @@ impl Spotify {
                 upc: None,
             },
             duration_ms: raw_track.duration_ms,
-            image: {
-                if raw_track.album.images.len() > 0 {
-                    Some(raw_track.album.images[0].url.to_owned())
-                } else {
-                    None
-                }
-            },
+            image: raw_track
+                .album
+                .images
+                .first()
+                .map(|image| image.url.to_owned()),
             audio_preview: raw_track.preview_url.to_owned(),
+            provenance: Some(provenance),
         })
     }
 
@@ -311,7 +631,8 @@ impl Spotify {
             artists.push(artist.name.to_owned());
         }
 
-        let mut release_date: std::str::Split<&str> = raw_track.album.release_date.split("-");
+        let (release_year, release_month, release_day) =
+            parse_release_date(&raw_track.album.release_date)?;
 
         Ok(Track {
             name: raw_track.name.to_owned(),
@@ -319,51 +640,193 @@ impl Spotify {
             disk_number: raw_track.disc_number,
             track_number: raw_track.track_number,
             artists,
-            release_year: match release_date.next() {
-                Some(year) => year.parse()?,
-                None => return Err(Error::DatabaseError("no release year".to_string())),
-            },
-            release_month: match release_date.next() {
-                Some(month) => Some(month.parse()?),
-                None => None,
-            },
-            release_day: match release_date.next() {
-                Some(day) => Some(day.parse()?),
-                None => None,
-            },
+            release_year,
+            release_month,
+            release_day,
             is_explicit: raw_track.explicit,
             duration_ms: raw_track.duration_ms,
             services: Services {
-                spotify: Some(Self::create_service_from_raw(raw_track).await?),
-                apple_music: None,
-                youtube: None,
-                bandcamp: None,
+                spotify: Some(
+                    Self::create_service_from_raw(
+                        raw_track,
+                        MatchProvenance::direct_id(&raw_track.id),
+                    )
+                    .await?,
+                ),
+                ..Default::default()
             },
             isrc: raw_track.external_ids.isrc.to_owned(),
             source_service: Source::Spotify,
         })
     }
 
+    async fn create_album_from_raw(
+        client: &Client,
+        config: &ServiceConfig,
+        auth: &dyn CredentialProvider,
+        raw_album: &RawAlbum,
+    ) -> Result<album::Album> {
+        // album track objects are missing ISRCs, so fetch the full tracks
+        let track_ids: Vec<&str> = raw_album
+            .tracks
+            .items
+            .iter()
+            .filter(|raw_album_track| !raw_album_track.is_local)
+            .map(|raw_album_track| raw_album_track.id.as_str())
+            .collect();
+
+        let mut tracks: Vec<Track> =
+            Self::create_tracks_from_ids(client, config, auth, &track_ids).await?;
+        tracks.sort_by_key(|track| (track.disk_number, track.track_number));
+
+        let (release_year, release_month, release_day) =
+            parse_release_date(&raw_album.release_date)?;
+
+        Ok(album::Album {
+            name: raw_album.name.to_owned(),
+            tracks,
+            id: raw_album.id.to_owned(),
+            source_service: Source::Spotify,
+            release_year,
+            release_month,
+            release_day,
+            artists: raw_album
+                .artists
+                .iter()
+                .map(|artist| artist.name.to_owned())
+                .collect(),
+            upc: raw_album.external_ids.upc.to_owned(),
+            label: raw_album.label.to_owned(),
+        })
+    }
+
+    /// Playlist items are full track objects, so nothing else is fetched
     async fn create_playlist_from_raw(raw_playlist: &RawPlaylist) -> Result<Playlist> {
-        let mut new_tracks_futures = Vec::new();
-        for raw_track in &raw_playlist.tracks.items {
-            new_tracks_futures.push(Self::create_track_from_raw(&raw_track.track));
+        let mut tracks: Vec<Track> = Vec::with_capacity(raw_playlist.tracks.items.len());
+        for (i, item) in raw_playlist.tracks.items.iter().enumerate() {
+            let raw_track: &Value = match &item.track {
+                Some(raw_track) if !item.is_local && raw_track["type"] == "track" => raw_track,
+                _ => {
+                    log::warn!(
+                        "\tSkipping playlist item ({}): local file, episode or unavailable track",
+                        i + 1
+                    );
+                    continue;
+                }
+            };
+            let track: Result<Track> = match serde_json::from_value(raw_track.to_owned()) {
+                Ok(raw_track) => Self::create_track_from_raw(&raw_track).await,
+                Err(e) => Err(e.into()),
+            };
+            match track {
+                Ok(track) => tracks.push(track),
+                Err(e) => log::warn!("\tSkipping playlist item ({}): {}", i + 1, e),
+            }
         }
 
-        let new_tracks_results = futures::future::join_all(new_tracks_futures).await;
-
-        let mut new_tracks: Playlist = Playlist {
+        Ok(Playlist {
             name: raw_playlist.name.to_owned(),
-            tracks: Vec::new(),
+            tracks,
             id: raw_playlist.id.to_owned(),
             description: raw_playlist.description.to_owned(),
             source_service: Source::Spotify,
-        };
-        for track_result in new_tracks_results {
-            new_tracks.tracks.push(track_result?);
+            curator: raw_playlist.owner["display_name"]
+                .as_str()
+                .map(str::to_owned),
+            image: raw_playlist
+                .images
+                .first()
+                .map(|image| image.url.to_owned()),
+        })
+    }
+}
+
+impl SpotifyProvider {
+    pub fn new(auth: &str) -> Self {
+        Self::with_config(auth, ServiceConfig::spotify())
+    }
+
+    pub fn with_config(auth: &str, config: ServiceConfig) -> Self {
+        Self::with_credentials(Arc::new(StaticToken(auth.to_owned())), config)
+    }
+
+    /// Get tokens from `credentials`, e.g. `SpotifyClientCredentials` or `SpotifyPkce`
+    pub fn with_credentials(
+        credentials: Arc<dyn CredentialProvider>,
+        config: ServiceConfig,
+    ) -> Self {
+        Self {
+            credentials,
+            config,
         }
+    }
+}
+
+#[async_trait]
+impl MusicService for SpotifyProvider {
+    fn name(&self) -> &str {
+        "Spotify"
+    }
 
-        Ok(new_tracks)
+    fn source(&self) -> Source {
+        Source::Spotify
+    }
+
+    fn storefront(&self) -> Option<&str> {
+        Some(&self.config.storefront)
+    }
+
+    fn service_record(&self, track: &Track) -> Option<Value> {
+        serde_json::to_value(track.services.spotify.as_ref()?).ok()
+    }
+
+    fn set_service_record(&self, track: &mut Track, record: Value) -> Result<()> {
+        track.services.spotify = Some(serde_json::from_value(record)?);
+        Ok(())
+    }
+
+    async fn create_track_from_id(&self, client: &Client, id: &str) -> Result<Track> {
+        Spotify::create_track_from_id(client, &self.config, self.credentials.as_ref(), id).await
+    }
+
+    async fn create_playlist_from_id(&self, client: &Client, id: &str) -> Result<Playlist> {
+        Spotify::create_playlist_from_id(client, &self.config, self.credentials.as_ref(), id).await
+    }
+
+    async fn create_album_from_id(&self, client: &Client, id: &str) -> Result<album::Album> {
+        Spotify::create_album_from_id(client, &self.config, self.credentials.as_ref(), id).await
+    }
+
+    async fn create_service_for_track(&self, client: &Client, track: &mut Track) -> Result<()> {
+        Spotify::create_service_for_track(client, &self.config, self.credentials.as_ref(), track)
+            .await
+    }
+
+    async fn create_service_from_id(
+        &self,
+        client: &Client,
+        track: &mut Track,
+        id: &str,
+    ) -> Result<()> {
+        track.services.spotify =
+            Spotify::create_track_from_id(client, &self.config, self.credentials.as_ref(), id)
+                .await?
+                .services
+                .spotify;
+        Ok(())
+    }
+
+    async fn search(&self, client: &Client, track: &Track) -> Result<Vec<MatchReport>> {
+        Spotify::search(client, &self.config, self.credentials.as_ref(), track).await
+    }
+
+    async fn create_services_for_tracks(
+        &self,
+        client: &Client,
+        tracks: &mut [Track],
+    ) -> Result<()> {
+        Spotify::create_services_for_tracks(client, &self.config, self.credentials.as_ref(), tracks)
+            .await
     }
 }
 
@@ -371,19 +834,30 @@ impl Spotify {
 mod tests {
 
     use crate::{
-        service::{Services, Source},
-        spotify::{SessionInfo, Spotify},
+        album::Album,
+        auth::SpotifyClientCredentials,
+        config::ServiceConfig,
+        error::Error,
+        matcher::Matcher,
+        playlist::Playlist,
+        replay::ReplayServer,
+        request::RequestPolicy,
+        service::{MatchMethod, Services, Source},
+        spotify::SpotifyProvider,
         track::Track,
     };
+    use std::sync::Arc;
+
+    async fn provider(cassette: &str) -> (ReplayServer, SpotifyProvider) {
+        let server: ReplayServer = ReplayServer::start(cassette, ServiceConfig::spotify()).await;
+        let provider: SpotifyProvider =
+            SpotifyProvider::with_config(&server.auth("SPOTIFY_TOKEN"), server.config());
+        (server, provider)
+    }
 
     #[tokio::test]
     async fn get_match_with_isrc() {
-        let example_services: Services = Services {
-            spotify: None,
-            apple_music: None,
-            youtube: None,
-            bandcamp: None,
-        };
+        let example_services: Services = Services::default();
 
         let mut example_track: Track = Track {
             name: "Duchess for Nothing".to_owned(),
@@ -405,23 +879,100 @@ mod tests {
                     .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15")
                     .build()
                     .unwrap();
-        let session_info: SessionInfo = Spotify::get_public_session_info(&client).await.unwrap();
+        let (_server, spotify) = provider("spotify/get_match_with_isrc").await;
 
-        example_track
-            .add_spotify(&client, &session_info.access_token)
-            .await
-            .unwrap();
+        example_track.add_service(&client, &spotify).await.unwrap();
+
+        let spotify = example_track.services.spotify.unwrap();
+        assert_eq!(spotify.id, "6Jd9HwGbIUVjQpHwQ0R9yL");
+        assert_eq!(spotify.album.name, "Genius Fatigue");
+        assert_eq!(spotify.artists[0].name, "Tunabunny");
+
+        let provenance = spotify.provenance.unwrap();
+        assert_eq!(provenance.method, MatchMethod::Isrc);
+        assert!(provenance.score.unwrap() >= Matcher::DEFAULT_THRESHOLD);
+        assert!(provenance.query.unwrap().starts_with("isrc:USZUD1215001"));
     }
 
     #[tokio::test]
-    async fn get_match_no_isrc() {
-        let example_services: Services = Services {
-            spotify: None,
-            apple_music: None,
-            youtube: None,
-            bandcamp: None,
+    async fn isrc_search_rate_limit_is_returned() {
+        let mut example_track: Track = Track {
+            name: "Duchess for Nothing".to_owned(),
+            album: "Genius Fatigue".to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_owned()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            services: Services::default(),
+            isrc: Some("USZUD1215001".to_owned()),
+            source_service: Source::Spotify,
         };
 
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("spotify/isrc_rate_limited", ServiceConfig::spotify()).await;
+        let spotify: SpotifyProvider = SpotifyProvider::with_config(
+            &server.auth("SPOTIFY_TOKEN"),
+            server.config().with_request_policy(RequestPolicy {
+                max_retries: 0,
+                ..Default::default()
+            }),
+        );
+
+        // no metadata search is recorded, so falling back to one would fail differently
+        let result = example_track.add_service(&client, &spotify).await;
+
+        assert!(matches!(result, Err(Error::RateLimited { .. })));
+        assert!(example_track.services.spotify.is_none());
+    }
+
+    #[tokio::test]
+    async fn token_is_refreshed_after_unauthorized() {
+        let mut example_track: Track = Track {
+            name: "Duchess for Nothing".to_owned(),
+            album: "Genius Fatigue".to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_owned()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            services: Services::default(),
+            isrc: Some("USZUD1215001".to_owned()),
+            source_service: Source::Spotify,
+        };
+
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("spotify/token_refresh", ServiceConfig::spotify()).await;
+        // the cassette rejects the first token and accepts the second
+        let spotify: SpotifyProvider = SpotifyProvider::with_credentials(
+            Arc::new(SpotifyClientCredentials::with_config(
+                "replay",
+                "replay",
+                server.config(),
+            )),
+            server.config(),
+        );
+
+        example_track.add_service(&client, &spotify).await.unwrap();
+
+        assert_eq!(
+            example_track.services.spotify.unwrap().id,
+            "6Jd9HwGbIUVjQpHwQ0R9yL"
+        );
+    }
+
+    #[tokio::test]
+    async fn get_match_no_isrc() {
+        let example_services: Services = Services::default();
+
         let mut example_track: Track = Track {
             name: "Duchess for Nothing".to_owned(),
             album: "Genius Fatigue".to_owned(),
@@ -442,11 +993,86 @@ mod tests {
                     .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15")
                     .build()
                     .unwrap();
-        let session_info: SessionInfo = Spotify::get_public_session_info(&client).await.unwrap();
+        let (_server, spotify) = provider("spotify/get_match_no_isrc").await;
+
+        example_track.add_service(&client, &spotify).await.unwrap();
+
+        let spotify = example_track.services.spotify.unwrap();
+        assert_eq!(spotify.id, "6Jd9HwGbIUVjQpHwQ0R9yL");
+        assert_eq!(spotify.provenance.unwrap().method, MatchMethod::FuzzySearch);
+    }
+
+    #[tokio::test]
+    async fn searches_fallback_storefronts() {
+        let mut example_track: Track = Track {
+            name: "Duchess for Nothing".to_owned(),
+            album: "Genius Fatigue".to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_owned()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            services: Services::default(),
+            isrc: None,
+            source_service: Source::AppleMusic,
+        };
+
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("spotify/fallback_storefront", ServiceConfig::spotify()).await;
+        let spotify: SpotifyProvider = SpotifyProvider::with_config(
+            &server.auth("SPOTIFY_TOKEN"),
+            server
+                .config()
+                .with_fallback_storefronts(&["us".to_string(), "gb".to_string()]),
+        );
+
+        example_track.add_service(&client, &spotify).await.unwrap();
+
+        let spotify = example_track.services.spotify.unwrap();
+        assert_eq!(spotify.id, "6Jd9HwGbIUVjQpHwQ0R9yL");
+    }
+
+    #[tokio::test]
+    async fn playlist_skips_unplayable_items() {
+        let client: reqwest::Client = reqwest::Client::builder()
+                    .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15")
+                    .build()
+                    .unwrap();
+        let (_server, spotify) = provider("spotify/playlist").await;
+
+        let playlist: Playlist =
+            Playlist::from_service_id(&client, &spotify, "3cEYpjA9oz9GiPac4AsH4n")
+                .await
+                .unwrap();
+
+        // local file, removed track and episode are skipped, second page is fetched
+        let names: Vec<&str> = playlist.tracks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["Duchess for Nothing", "Canned Laughter"]);
+        assert_eq!(playlist.curator.as_deref(), Some("songvert"));
+        assert_eq!(playlist.tracks[0].isrc.as_deref(), Some("USZUD1215001"));
+        assert_eq!(playlist.tracks[0].release_month, Some(2));
+    }
+
+    #[tokio::test]
+    async fn album_is_sorted_and_complete() {
+        let client: reqwest::Client = reqwest::Client::builder()
+                    .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15")
+                    .build()
+                    .unwrap();
+        let (_server, spotify) = provider("spotify/album").await;
 
-        example_track
-            .add_spotify(&client, &session_info.access_token)
+        let album: Album = Album::from_service_id(&client, &spotify, "0sNxQhDTG4vCahzPTjCGKg")
             .await
             .unwrap();
+
+        let numbers: Vec<usize> = album.tracks.iter().map(|t| t.track_number).collect();
+        assert_eq!(numbers, [1, 2]);
+        assert_eq!(album.artists, ["Tunabunny"]);
+        assert_eq!(album.upc.as_deref(), Some("656605100113"));
+        assert_eq!(album.label.as_deref(), Some("HHBTM Records"));
     }
 }
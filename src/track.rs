@@ -1,15 +1,21 @@
-use crate::apple_music::AppleMusic;
-use crate::bandcamp::Bandcamp;
+use crate::apple_music::AppleMusicProvider;
+use crate::bandcamp::BandcampProvider;
+use crate::cache::MatchCache;
+use crate::config::Config;
+use crate::deezer::DeezerProvider;
 use crate::error::{Error, Result};
-use crate::service::{Services, Source};
-use crate::spotify::Spotify;
+use crate::matcher::MatchReport;
+use crate::overrides::{with_overrides_and_cache, Overrides};
+use crate::service::{MusicService, Services, Source};
+use crate::spotify::SpotifyProvider;
 use crate::utils::{add_metadata_to_m4a, add_metadata_to_mp3};
-use crate::youtube::YouTube;
+use crate::youtube::YouTubeProvider;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct Track {
@@ -29,106 +35,114 @@ pub struct Track {
 }
 
 impl Track {
-    pub async fn add_spotify(&mut self, client: &Client, auth: &str) -> Result<()> {
-        Spotify::create_service_for_track(client, auth, self).await?;
-
-        Ok(())
+    pub async fn add_service(&mut self, client: &Client, service: &dyn MusicService) -> Result<()> {
+        service.create_service_for_track(client, self).await
     }
 
-    pub async fn add_apple_music(&mut self, client: &Client, auth: &str) -> Result<()> {
-        AppleMusic::create_service_for_track(client, auth, self).await?;
-
-        Ok(())
+    /// Every result `service` finds for this track, best match first
+    pub async fn search_service(
+        &self,
+        client: &Client,
+        service: &dyn MusicService,
+    ) -> Result<Vec<MatchReport>> {
+        service.search(client, self).await
     }
 
-    pub async fn add_youtube(&mut self, client: &Client) -> Result<()> {
-        YouTube::create_service_for_track(client, self).await?;
-
-        Ok(())
+    /// Add `service`, applying `overrides` and then answering from `cache` before
+    /// searching. The cache is saved afterwards since shared caches never drop.
+    async fn add_wrapped_service(
+        &mut self,
+        client: &Client,
+        service: Box<dyn MusicService>,
+        overrides: Arc<Overrides>,
+        cache: Option<Arc<MatchCache>>,
+    ) -> Result<()> {
+        let service = with_overrides_and_cache(service, overrides, cache.clone());
+        let result: Result<()> = self.add_service(client, &service).await;
+        if let Some(cache) = cache {
+            cache.flush();
+        }
+        result
     }
 
-    pub async fn add_bandcamp(&mut self, client: &Client) -> Result<()> {
-        Bandcamp::create_service_for_track(client, self).await?;
-
-        Ok(())
+    pub async fn add_spotify(&mut self, client: &Client, auth: &str) -> Result<()> {
+        self.add_wrapped_service(
+            client,
+            Box::new(SpotifyProvider::new(auth)),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
     }
 
-    pub fn compare_similarity_fuzzy(
-        &self,
-        compare_name: &str,
-        compare_artist: &str,
-        compare_album: &str,
-        compare_duration_ms: usize,
-    ) -> f64 {
-        let mut count: f64 = 0.0;
-
-        count += strsim::jaro_winkler(&self.name.to_lowercase(), &compare_name.to_lowercase());
-        count += strsim::jaro_winkler(&self.album.to_lowercase(), &compare_album.to_lowercase());
-
-        if self.artists.len() > 0 {
-            count += strsim::jaro_winkler(
-                &self.artists[0].to_lowercase(),
-                &compare_artist.to_lowercase(),
-            );
-        }
-
-        if compare_duration_ms.abs_diff(self.duration_ms) <= 3000 {
-            // no more than 3 second difference
-            count += 1.0;
-        }
-        count
+    pub async fn add_apple_music(&mut self, client: &Client, auth: &str) -> Result<()> {
+        self.add_wrapped_service(
+            client,
+            Box::new(AppleMusicProvider::new(auth)),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
     }
 
-    pub fn compare_similarity(
-        &self,
-        compare_name: &str,
-        compare_artist: &str,
-        compare_album: &str,
-        compare_duration_ms: usize,
-    ) -> u8 {
-        let mut count = 0;
-        if compare_name.to_lowercase() == self.name.to_lowercase() {
-            count += 1;
-        }
-
-        if compare_album.to_lowercase() == self.album.to_lowercase() {
-            count += 1;
-        }
-
-        if self.artists.len() > 0 {
-            if compare_artist.to_lowercase() == self.artists[0].to_lowercase() {
-                count += 1;
-            }
-        }
+    pub async fn add_youtube(&mut self, client: &Client) -> Result<()> {
+        self.add_wrapped_service(
+            client,
+            Box::new(YouTubeProvider::new()),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
+    }
 
-        if compare_duration_ms.abs_diff(self.duration_ms) <= 3000 {
-            // no more than 3 second difference
-            count += 1;
-        }
+    pub async fn add_bandcamp(&mut self, client: &Client) -> Result<()> {
+        self.add_wrapped_service(
+            client,
+            Box::new(BandcampProvider::new()),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
+    }
 
-        count
+    pub async fn add_deezer(&mut self, client: &Client) -> Result<()> {
+        self.add_wrapped_service(
+            client,
+            Box::new(DeezerProvider::new()),
+            Overrides::shared_default(),
+            MatchCache::shared_default(),
+        )
+        .await
     }
 
     pub async fn download(
         &self,
         client: &Client,
+        config: &Config,
         path: &Path,
         filename: &str,
         add_metadata: bool,
     ) -> Result<()> {
-        if let Some(bandcamp) = &self.services.bandcamp {
-            if let Ok(download_path) = bandcamp.download(client, path, filename).await {
-                if add_metadata == true {
-                    add_metadata_to_mp3(client, &download_path, &self, false).await?;
-                }
-                return Ok(());
-            }
-        }
+        // in order of preference
+        let bandcamp = BandcampProvider::with_config(config.bandcamp.clone());
+        let youtube = YouTubeProvider::with_config(config.youtube.clone());
+        let services: [&dyn MusicService; 2] = [&bandcamp, &youtube];
 
-        if let Some(youtube) = &self.services.youtube {
-            if let Ok(download_path) = youtube.download(path, filename).await {
-                if add_metadata == true {
-                    add_metadata_to_m4a(client, &download_path, &self, false).await?;
+        for service in services {
+            if let Ok(download_path) = service.download(client, self, path, filename).await {
+                if add_metadata {
+                    match download_path.extension().and_then(|e| e.to_str()) {
+                        Some("mp3") => {
+                            add_metadata_to_mp3(client, config, &download_path, self, false).await?
+                        }
+                        Some("m4a") => {
+                            add_metadata_to_m4a(client, config, &download_path, self, false).await?
+                        }
+                        _ => log::warn!(
+                            "\tUnable to add metadata to {}",
+                            download_path.to_string_lossy()
+                        ),
+                    }
                 }
                 return Ok(());
             }
@@ -142,15 +156,25 @@ impl Track {
         Err(Error::DownloadError("download failed".to_string()))
     }
 
+    pub async fn from_service_id(
+        client: &Client,
+        service: &dyn MusicService,
+        track_id: &str,
+    ) -> Result<Self> {
+        service.create_track_from_id(client, track_id).await
+    }
+
     pub async fn from_spotify_id(
         client: &Client,
         spotify_auth: &str,
         spotify_track_id: &str,
     ) -> Result<Self> {
-        let track: Self =
-            Spotify::create_track_from_id(client, spotify_auth, spotify_track_id).await?;
-
-        Ok(track)
+        Self::from_service_id(
+            client,
+            &SpotifyProvider::new(spotify_auth),
+            spotify_track_id,
+        )
+        .await
     }
 
     pub async fn from_apple_music_id(
@@ -158,11 +182,24 @@ impl Track {
         apple_music_auth: &str,
         apple_music_track_id: &str,
     ) -> Result<Self> {
-        let track: Self =
-            AppleMusic::create_track_from_id(client, apple_music_auth, apple_music_track_id)
-                .await?;
+        Self::from_service_id(
+            client,
+            &AppleMusicProvider::new(apple_music_auth),
+            apple_music_track_id,
+        )
+        .await
+    }
+
+    pub async fn from_youtube_id(client: &Client, youtube_track_id: &str) -> Result<Self> {
+        Self::from_service_id(client, &YouTubeProvider::new(), youtube_track_id).await
+    }
+
+    pub async fn from_deezer_id(client: &Client, deezer_track_id: &str) -> Result<Self> {
+        Self::from_service_id(client, &DeezerProvider::new(), deezer_track_id).await
+    }
 
-        Ok(track)
+    pub async fn from_bandcamp_url(client: &Client, bandcamp_track_url: &str) -> Result<Self> {
+        Self::from_service_id(client, &BandcampProvider::new(), bandcamp_track_url).await
     }
 
     pub fn from_file(file_path: &Path) -> Result<Self> {
@@ -187,4 +224,121 @@ impl Track {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+
+    use crate::{
+        cache::MatchCache,
+        config::ServiceConfig,
+        overrides::{Override, Overrides},
+        replay::ReplayServer,
+        request::RequestPolicy,
+        service::{Services, Source},
+        spotify::SpotifyProvider,
+        track::Track,
+        youtube::YouTubeProvider,
+    };
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn example_track(isrc: Option<&str>) -> Track {
+        Track {
+            name: "Duchess for Nothing".to_owned(),
+            album: "Genius Fatigue".to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_owned()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            services: Services::default(),
+            isrc: isrc.map(str::to_owned),
+            source_service: Source::Spotify,
+        }
+    }
+
+    fn no_overrides() -> Arc<Overrides> {
+        Arc::new(Overrides::open(&std::env::temp_dir().join("songvert-no-overrides.json")).unwrap())
+    }
+
+    #[tokio::test]
+    async fn add_helpers_apply_overrides() {
+        // never written, the override only lives in memory
+        let mut overrides: Overrides = Overrides::open(
+            &std::env::temp_dir().join("songvert-add_helpers_apply_overrides.json"),
+        )
+        .unwrap();
+        overrides.insert(
+            &Overrides::isrc_key("USZUD1215001"),
+            "YouTube",
+            Override::Skip,
+        );
+
+        let mut track: Track = example_track(Some("USZUD1215001"));
+
+        // the skip means YouTube is never searched, so this stays offline
+        track
+            .add_wrapped_service(
+                &reqwest::Client::new(),
+                Box::new(YouTubeProvider::new()),
+                Arc::new(overrides),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(track.services.youtube.is_none());
+    }
+
+    #[tokio::test]
+    async fn add_helpers_answer_from_cache() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "songvert-add_helpers_answer_from_cache-{}.json",
+            std::process::id()
+        ));
+        let cache: Arc<MatchCache> = Arc::new(MatchCache::open(&path));
+
+        let server: ReplayServer =
+            ReplayServer::start("spotify/get_match_with_isrc", ServiceConfig::spotify()).await;
+        let mut first: Track = example_track(Some("USZUD1215001"));
+        let first_result = first
+            .add_wrapped_service(
+                &client,
+                Box::new(SpotifyProvider::with_config(
+                    &server.auth("SPOTIFY_TOKEN"),
+                    server.config(),
+                )),
+                no_overrides(),
+                Some(cache.clone()),
+            )
+            .await;
+
+        // nothing is listening here, so only the cache can answer
+        let mut second: Track = example_track(None);
+        let second_result = second
+            .add_wrapped_service(
+                &client,
+                Box::new(SpotifyProvider::with_config(
+                    "replay",
+                    ServiceConfig::spotify()
+                        .with_base_url("http://127.0.0.1:9")
+                        .with_request_policy(RequestPolicy {
+                            max_retries: 0,
+                            ..Default::default()
+                        }),
+                )),
+                no_overrides(),
+                Some(cache.clone()),
+            )
+            .await;
+        let saved: bool = path.exists();
+        cache.clear().unwrap();
+
+        first_result.unwrap();
+        second_result.unwrap();
+        assert!(saved);
+        assert_eq!(second.services.spotify, first.services.spotify);
+        assert!(second.services.spotify.is_some());
+    }
+}
@@ -1,13 +1,17 @@
 use crate::{
+    config::{Config, ServiceConfig},
     error::{Error, Result},
     track::Track,
 };
 use id3::TagLike;
-use reqwest::Client;
-use std::path::Path;
+use reqwest::{Client, Method};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
 
 pub async fn add_metadata_to_mp3(
     client: &Client,
+    config: &Config,
     mp3_file_path: &Path,
     track: &Track,
     overwrite_artwork: bool,
@@ -16,7 +20,7 @@ pub async fn add_metadata_to_mp3(
         Ok(tag) => tag,
         Err(e) => match e.kind {
             id3::ErrorKind::NoTag => id3::Tag::new(),
-            _ => return Err(Error::TagError(e.description)),
+            _ => return Err(e.into()),
         },
     };
 
@@ -25,9 +29,16 @@ pub async fn add_metadata_to_mp3(
 
     tag.set_title(track.name.to_owned());
     tag.set_album(track.album.to_owned());
-    tag.set_artist(track.artists.get(0).ok_or(Error::TrackError(
-        "Track requires at least one artist".to_string(),
-    ))?);
+    if track.artists.is_empty() {
+        return Err(Error::TrackError(
+            "Track requires at least one artist".to_string(),
+        ));
+    }
+    // only ID3v2.4 has multiple values per frame, older readers expect `/`
+    match tag.version() {
+        id3::Version::Id3v24 => tag.set_text_values("TPE1", &track.artists),
+        _ => tag.set_artist(track.artists.join("/")),
+    }
     //println!("year: {:?}", tag.year());
     //println!("recorded_year: {:?}", tag.date_recorded());
     //println!("release_year: {:?}", tag.date_released());
@@ -54,17 +65,13 @@ pub async fn add_metadata_to_mp3(
     // second: None,
     //});
 
-    if overwrite_artwork == false {
-        if let Some(_) = tag.pictures().next() {
-            log::info!("{} already has an image", mp3_file_path.to_string_lossy());
-            match tag.write_to_path(mp3_file_path, tag.version()) {
-                Ok(..) => return Ok(()),
-                Err(e) => return Err(Error::TagError(e.description)),
-            }
-        }
+    if !overwrite_artwork && tag.pictures().next().is_some() {
+        log::info!("{} already has an image", mp3_file_path.to_string_lossy());
+        tag.write_to_path(mp3_file_path, tag.version())?;
+        return Ok(());
     }
 
-    if let Ok(bytes) = download_best_artwork_bytes(client, track).await {
+    if let Ok(bytes) = download_best_artwork_bytes(client, config, track).await {
         tag.remove_all_pictures();
 
         tag.add_frame(id3::frame::Picture {
@@ -75,73 +82,68 @@ pub async fn add_metadata_to_mp3(
         });
     }
 
-    match tag.write_to_path(mp3_file_path, tag.version()) {
-        Ok(..) => Ok(()),
-        Err(e) => Err(Error::TagError(e.description)),
-    }
+    tag.write_to_path(mp3_file_path, tag.version())?;
+    Ok(())
 }
 
 pub async fn add_metadata_to_m4a(
     client: &Client,
+    config: &Config,
     m4a_file_path: &Path,
     track: &Track,
     overwrite_artwork: bool,
 ) -> Result<()> {
-    let mut tag = match mp4ameta::Tag::read_from_path(m4a_file_path) {
-        Ok(tag) => tag,
-        Err(e) => return Err(Error::TagError(e.description)),
-    };
+    let mut tag = mp4ameta::Tag::read_from_path(m4a_file_path)?;
 
     tag.set_title(track.name.to_owned());
     tag.set_album(track.album.to_owned());
-    tag.set_artist(track.artists.get(0).ok_or(Error::TrackError(
-        "Track requires at least one artist".to_string(),
-    ))?);
+    if track.artists.is_empty() {
+        return Err(Error::TrackError(
+            "Track requires at least one artist".to_string(),
+        ));
+    }
+    tag.set_artists(track.artists.iter().cloned());
     tag.set_year(track.release_year.to_string());
 
-    if overwrite_artwork == false {
-        if let Some(_) = tag.images().next() {
-            log::info!("{} already has an image", m4a_file_path.to_string_lossy());
-            match tag.write_to_path(m4a_file_path) {
-                Ok(..) => return Ok(()),
-                Err(e) => return Err(Error::TagError(e.description)),
-            }
-        }
+    if !overwrite_artwork && tag.images().next().is_some() {
+        log::info!("{} already has an image", m4a_file_path.to_string_lossy());
+        tag.write_to_path(m4a_file_path)?;
+        return Ok(());
     }
 
-    if let Ok(bytes) = download_best_artwork_bytes(client, track).await {
+    if let Ok(bytes) = download_best_artwork_bytes(client, config, track).await {
         tag.add_artwork(mp4ameta::Img::new(mp4ameta::ImgFmt::Jpeg, bytes));
     }
 
-    match tag.write_to_path(m4a_file_path) {
-        Ok(..) => Ok(()),
-        Err(e) => Err(Error::TagError(e.description)),
-    }
+    tag.write_to_path(m4a_file_path)?;
+    Ok(())
 }
 
-async fn download_best_artwork_bytes(client: &Client, track: &Track) -> Result<Vec<u8>> {
-    let mut token: Option<&str> = None;
-    let mut artwork_url: Option<String> = None;
+async fn download_best_artwork_bytes(
+    client: &Client,
+    config: &Config,
+    track: &Track,
+) -> Result<Vec<u8>> {
+    let mut artwork: Option<(&ServiceConfig, String)> = None;
 
     if let Some(apple_music) = &track.services.apple_music {
         if let Some(url) = &apple_music.image {
-            token = Some(crate::apple_music::AppleMusic::PUBLIC_BEARER_TOKEN);
-            artwork_url = Some(url.replace(".webp", ".jpg"));
+            artwork = Some((&config.apple_music, url.replace(".webp", ".jpg")));
         }
     } else if let Some(spotify) = &track.services.spotify {
         if let Some(url) = &spotify.image {
-            artwork_url = Some(url.to_owned());
+            artwork = Some((&config.spotify, url.to_owned()));
+        }
+    } else if let Some(deezer) = &track.services.deezer {
+        if let Some(url) = &deezer.image {
+            artwork = Some((&config.deezer, url.to_owned()));
         }
     }
 
-    if let Some(url) = artwork_url {
-        let response = match token {
-            Some(token) => client.get(url).bearer_auth(token),
-            None => client.get(url),
-        }
-        .send()
-        .await?
-        .error_for_status()?;
+    if let Some((service_config, url)) = artwork {
+        let request =
+            service_config.request_url(client, Method::GET, &service_config.media_url(&url));
+        let response = service_config.send(request).await?;
 
         Ok(response.bytes().await?.to_vec())
     } else {
@@ -150,3 +152,61 @@ async fn download_best_artwork_bytes(client: &Client, track: &Track) -> Result<V
         ))
     }
 }
+
+/// `value` from `service`'s response to `path` as a `T`, or
+/// `Error::UnexpectedResponse` showing the start of it
+pub fn parse_response<T: DeserializeOwned>(service: &str, path: &str, value: &Value) -> Result<T> {
+    T::deserialize(value).map_err(|_| Error::unexpected_response(service, path, &value.to_string()))
+}
+
+/// Split a `YYYY`, `YYYY-MM` or `YYYY-MM-DD` release date into its parts
+pub fn parse_release_date(release_date: &str) -> Result<(usize, Option<usize>, Option<usize>)> {
+    let mut parts = release_date.split('-');
+
+    let year: usize = match parts.next() {
+        Some(year) => year.parse()?,
+        None => return Err(Error::DatabaseError("no release year".to_string())),
+    };
+    let month: Option<usize> = parts.next().map(str::parse).transpose()?;
+    let day: Option<usize> = parts.next().map(str::parse).transpose()?;
+
+    Ok((year, month, day))
+}
+
+/// Year, month and day (UTC) of a Unix `timestamp` in seconds
+pub fn date_from_timestamp(timestamp: u64) -> (usize, usize, usize) {
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days: u64 = timestamp / 86_400 + 719_468;
+    let era: u64 = days / 146_097;
+    let day_of_era: u64 = days % 146_097;
+    let year_of_era: u64 =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year: u64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month: u64 = (5 * day_of_year + 2) / 153;
+    let day: u64 = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month: u64 = match shifted_month < 10 {
+        true => shifted_month + 3,
+        false => shifted_month - 9,
+    };
+    let year: u64 = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year as usize, month as usize, day as usize)
+}
+
+/// `$XDG_CACHE_HOME/songvert`, falling back to `~/.cache/songvert`
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache_home: PathBuf = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache_home.join("songvert"))
+}
+
+/// `$XDG_CONFIG_HOME/songvert`, falling back to `~/.config/songvert`
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home: PathBuf = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("songvert"))
+}
@@ -1,7 +1,14 @@
+use crate::album;
+use crate::config::ServiceConfig;
 use crate::error::{Error, Result};
-use crate::service::{Album, Artist};
+use crate::matcher::{Candidate, MatchReport, Matcher};
+use crate::normalize::{search_artists, search_title};
+use crate::playlist::Playlist;
+use crate::service::{Album, Artist, MatchMethod, MatchProvenance, MusicService, Services, Source};
 use crate::track::Track;
-use reqwest::{Client, RequestBuilder, Response};
+use crate::utils::parse_response;
+use async_trait::async_trait;
+use reqwest::{Client, Method, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::path::{Path, PathBuf};
@@ -17,105 +24,90 @@ pub struct YouTube {
     pub duration_ms: usize,
     pub music_video: Option<String>,
     pub thumbnails: Vec<String>,
+    /// How this record was matched to its track
+    pub provenance: Option<MatchProvenance>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[serde(rename_all(deserialize = "camelCase"))]
-struct RawStreamingData {
-    expires_in_seconds: String,
-    formats: Vec<RawFormat>,
-    adaptive_formats: Vec<RawAdaptiveFormat>,
-    server_abr_streaming_url: String,
+/// `MusicService` implementation for YouTube Music. Downloading requires `yt-dlp`.
+pub struct YouTubeProvider {
+    pub config: ServiceConfig,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
-struct RawFormat {
-    itag: usize,
-    mime_type: String,
-    bitrate: usize,
-    // pub width: usize,
-    // pub height: usize,
-    last_modified: String,
-    quality: String,
-    fps: Option<usize>,
-    quality_label: Option<String>,
-    projection_type: String,
-    audio_quality: String,
-    approx_duration_ms: String,
-    audio_sample_rate: String,
-    audio_channels: usize,
-    signature_cipher: String,
+struct RawMusicShelfRenderer {
+    title: RawTextRuns,
+    contents: Vec<RawMusicShelfRendererContent>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
-struct RawAdaptiveFormat {
-    itag: usize,
-    mime_type: String,
-    bitrate: usize,
-    // pub width: usize,
-    // pub height: usize,
-    init_range: RawRange,
-    index_range: RawRange,
-    last_modified: String,
-    content_length: String,
-    quality: String,
-    fps: Option<usize>,
-    quality_label: Option<String>,
-    projection_type: String,
-    average_bitrate: usize,
-    approx_duration_ms: String,
-    signature_cipher: String,
-    // pub color_info: Option<Value>,
+struct RawMusicShelfRendererContent {
+    music_responsive_list_item_renderer: RawMusicResponsiveListItemRenderer,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
-struct RawRange {
-    start: String,
-    end: String,
+struct RawMusicResponsiveListItemRenderer {
+    thumbnail: Option<RawOuterThumbnail>,
+    flex_columns: Vec<RawFlexColumn>,
+    /// Duration, in album and playlist rows
+    fixed_columns: Option<Vec<RawFixedColumn>>,
+    /// Track number, in album rows
+    index: Option<RawTextRuns>,
+    playlist_item_data: Option<RawPlaylistItemData>,
+    badges: Option<Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
-struct RawMusicShelfRenderer {
-    title: RawTextRuns,
-    contents: Vec<RawMusicShelfRendererContent>,
+struct RawFlexColumn {
+    music_responsive_list_item_flex_column_renderer: RawMusicResponsiveListItemFlexColumnRenderer,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
-struct RawMusicShelfRendererContent {
-    music_responsive_list_item_renderer: RawMusicResponsiveListItemRenderer,
+struct RawMusicResponsiveListItemFlexColumnRenderer {
+    text: RawTextRuns,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
-struct RawMusicResponsiveListItemRenderer {
-    thumbnail: RawOuterThumbnail,
-    flex_columns: Vec<RawFlexColumn>,
+struct RawFixedColumn {
+    music_responsive_list_item_fixed_column_renderer: RawMusicResponsiveListItemFlexColumnRenderer,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
-struct RawFlexColumn {
-    music_responsive_list_item_flex_column_renderer: RawMusicResponsiveListItemFlexColumnRenderer,
+struct RawPlaylistItemData {
+    video_id: String,
 }
 
+/// A song in the queue returned by the `next` endpoint
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
-struct RawMusicResponsiveListItemFlexColumnRenderer {
-    text: RawTextRuns,
+struct RawPlaylistPanelVideoRenderer {
+    video_id: String,
+    title: RawTextRuns,
+    /// `Artist • Album • 2013`
+    long_byline_text: RawTextRuns,
+    length_text: Option<RawTextRuns>,
+    thumbnail: RawInnerThumbnail,
+    badges: Option<Value>,
 }
 
+/// Header of an album or playlist page, either `musicResponsiveHeaderRenderer`
+/// or the older `musicDetailHeaderRenderer`
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
-struct RawMusicCardShelfRenderer {
-    thumbnail: RawOuterThumbnail,
+struct RawBrowseHeader {
     title: RawTextRuns,
-    subtitle: RawTextRuns,
-    header: RawHeader,
+    /// `Album • 2013`, or `Album • Artist • 2013` in the older header
+    subtitle: Option<RawTextRuns>,
+    /// Artists of an album or owner of a playlist
+    strapline_text_one: Option<RawTextRuns>,
+    description: Option<Value>,
+    thumbnail: Option<Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -144,21 +136,10 @@ struct RawThumbnail {
     height: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[serde(rename_all(deserialize = "camelCase"))]
-struct RawHeader {
-    music_card_shelf_header_basic_renderer: RawMusicCardShelfHeaderBasicRenderer,
-}
-
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-#[serde(rename_all(deserialize = "camelCase"))]
-struct RawMusicCardShelfHeaderBasicRenderer {
-    title: RawTextRuns,
-}
-
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all(deserialize = "camelCase"))]
 struct RawTextRuns {
+    #[serde(default)]
     runs: Vec<RawRun>,
 }
 
@@ -195,6 +176,10 @@ pub struct Payload<'a> {
     pub video_id: Option<&'a str>,
     pub query: Option<&'a str>,
     pub params: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browse_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continuation: Option<&'a str>,
 }
 
 #[derive(Serialize, Debug, PartialEq)]
@@ -214,6 +199,9 @@ pub struct PayloadContextClient<'a> {
 impl YouTube {
     pub const API_BASE_URL: &'static str = "https://www.youtube.com/youtubei/v1";
     pub const SITE_BASE_URL: &'static str = "https://www.youtube.com";
+    /// Slightly below `Matcher::DEFAULT_THRESHOLD`, since search results only
+    /// carry a title, artist, album and duration
+    pub const MATCH_THRESHOLD: f64 = 0.725;
     pub const DEFAULT_MUSIC_PAYLOAD_CONTEXT: PayloadContext<'static> = PayloadContext {
         client: PayloadContextClient {
             hl: "en",
@@ -223,40 +211,86 @@ impl YouTube {
         },
     };
 
-    async fn post<'a>(client: &Client, path: &str, body: &Payload<'a>) -> Result<Value> {
+    /// `DEFAULT_MUSIC_PAYLOAD_CONTEXT` localized to the storefront and language of `config`
+    fn music_payload_context(config: &ServiceConfig) -> PayloadContext<'_> {
+        PayloadContext {
+            client: PayloadContextClient {
+                hl: &config.language,
+                gl: &config.storefront,
+                ..Self::DEFAULT_MUSIC_PAYLOAD_CONTEXT.client
+            },
+        }
+    }
+
+    async fn post<'a>(
+        client: &Client,
+        config: &ServiceConfig,
+        path: &str,
+        body: &Payload<'a>,
+    ) -> Result<Value> {
         let raw_payload: String = serde_json::to_string(&body)?;
 
-        let request: RequestBuilder = client
-            .post(format!("{}/{}", Self::API_BASE_URL, path))
-            .body(raw_payload);
+        let request: RequestBuilder = config.request(client, Method::POST, path).body(raw_payload);
 
-        let mut response: Response = request.send().await?;
-        response = response.error_for_status()?;
+        let response: Response = config.send(request).await?;
 
-        let data: serde_json::Value = serde_json::from_str(&response.text().await?)?;
+        let body: String = response.text().await?;
+        let data: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|_| Error::unexpected_response("YouTube", path, &body))?;
 
         Ok(data)
     }
 
-    async fn get_raw_results_from_search(client: &Client, query: &str) -> Result<Value> {
+    async fn get_raw_results_from_search(
+        client: &Client,
+        config: &ServiceConfig,
+        query: &str,
+    ) -> Result<Value> {
         let payload: Payload = Payload {
-            context: Self::DEFAULT_MUSIC_PAYLOAD_CONTEXT,
+            context: Self::music_payload_context(config),
             video_id: None,
             query: Some(query),
             //params: Some("EgWKAQIIAWoGEAMQChAQ"), // old
             params: Some("EgWKAQIIAUICCAFqBBADEAk"), // this should (among other things) keep API from autocorrecting
+            browse_id: None,
+            continuation: None,
         };
-        Self::post(client, "search", &payload).await
+        Self::post(client, config, "search", &payload).await
     }
 
-    async fn get_raw_track_from_id(client: &Client, id: &str) -> Result<Value> {
+    async fn get_raw_track_from_id(
+        client: &Client,
+        config: &ServiceConfig,
+        id: &str,
+    ) -> Result<Value> {
         let payload: Payload = Payload {
-            context: Self::DEFAULT_MUSIC_PAYLOAD_CONTEXT,
+            context: Self::music_payload_context(config),
             video_id: Some(id),
             query: None,
-            params: Some("EgWKAQIIAWoGEAMQChAQ"),
+            params: None,
+            browse_id: None,
+            continuation: None,
+        };
+        Self::post(client, config, "next", &payload).await
+    }
+
+    /// Browse the page `browse_id` (e.g. `MPREb_…` or `VL…`), or continue a long
+    /// list from its `continuation` token
+    async fn get_raw_browse(
+        client: &Client,
+        config: &ServiceConfig,
+        browse_id: Option<&str>,
+        continuation: Option<&str>,
+    ) -> Result<Value> {
+        let payload: Payload = Payload {
+            context: Self::music_payload_context(config),
+            video_id: None,
+            query: None,
+            params: None,
+            browse_id,
+            continuation,
         };
-        Self::post(client, "next", &payload).await
+        Self::post(client, config, "browse", &payload).await
     }
 
     pub async fn download(&self, path: &Path, filename: &str) -> Result<PathBuf> {
@@ -274,50 +308,117 @@ impl YouTube {
             .status()
         {
             Ok(status) => {
-                if status.success() == false {
+                if !status.success() {
                     Err(Error::DownloadError("download failed".to_string()))
                 } else {
                     Ok(full_path)
                 }
             }
-            Err(e) => Err(Error::DownloadError(format!("{}", e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(Error::ExternalToolMissing("yt-dlp".to_string()))
+            }
+            Err(e) => Err(e.into()),
         }
     }
 
-    async fn get_raw_track_match_from_track(client: &Client, track: &Track) -> Result<Value> {
-        let query: String = format!(
+    /// Query searching for `track`'s metadata
+    fn search_query(track: &Track) -> Result<String> {
+        Ok(format!(
             "{}, {}, {}, {}",
-            track.name,
-            track.artists.get(0).ok_or(Error::TrackError(
-                "Track requires at least one artist".to_string(),
-            ))?,
+            search_title(&track.name),
+            search_artists(&track.artists)?,
             track.release_year,
-            track.album,
-        );
+            search_title(&track.album),
+        ))
+    }
 
+    async fn get_raw_track_match_from_track(
+        client: &Client,
+        config: &ServiceConfig,
+        query: &str,
+    ) -> Result<Value> {
         Ok(
-            Self::get_raw_results_from_search(client, &query).await?["contents"]
+            Self::get_raw_results_from_search(client, config, query).await?["contents"]
                 ["tabbedSearchResultsRenderer"]["tabs"]
                 .get_mut(0)
-                .ok_or(Error::TrackError("no match found".to_string()))?["tabRenderer"]["content"]
-                ["sectionListRenderer"]["contents"]
+                .ok_or(Error::NotFound {
+                    service: "YouTube".to_string(),
+                    query: query.to_owned(),
+                })?["tabRenderer"]["content"]["sectionListRenderer"]["contents"]
                 .take(),
         )
     }
 
-    pub async fn create_service_for_track(client: &Client, track: &mut Track) -> Result<()> {
-        let data: Value = Self::get_raw_track_match_from_track(client, track).await?;
-        let service: Self = Self::create_service_from_raw(&data, track).await?;
+    /// Songs found by searching for `query`, ranked against `track`
+    async fn get_ranked_services_from_search(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &Track,
+        query: &str,
+    ) -> Result<Vec<(MatchReport, Self)>> {
+        let data: Value = Self::get_raw_track_match_from_track(client, config, query).await?;
+        Ok(config
+            .matcher
+            .rank(track, Self::create_candidates_from_raw(&data, query)?))
+    }
+
+    /// Every song found by searching for `track`'s metadata, best match first
+    pub async fn search(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &Track,
+    ) -> Result<Vec<MatchReport>> {
+        Ok(Self::get_ranked_services_from_search(
+            client,
+            config,
+            track,
+            &Self::search_query(track)?,
+        )
+        .await?
+        .into_iter()
+        .map(|(report, _)| report)
+        .collect())
+    }
+
+    pub async fn create_service_for_track(
+        client: &Client,
+        config: &ServiceConfig,
+        track: &mut Track,
+    ) -> Result<()> {
+        let query: &str = &Self::search_query(track)?;
+        let search_track: &Track = track;
+        let (report, mut service): (MatchReport, Self) = config
+            .find_in_storefronts(|config| async move {
+                Matcher::best_match(
+                    Self::get_ranked_services_from_search(client, &config, search_track, query)
+                        .await?,
+                )
+                .ok_or(Error::NotFound {
+                    service: "YouTube".to_string(),
+                    query: format!(
+                        "{} - {}",
+                        search_track.name,
+                        search_track.artists.join(", ")
+                    ),
+                })
+            })
+            .await?;
+        service.provenance = Some(MatchProvenance::from_report(
+            MatchMethod::FuzzySearch,
+            &report,
+            query,
+        ));
         track.services.youtube = Some(service);
         Ok(())
     }
 
-    async fn create_service_from_raw(data: &Value, track: &Track) -> Result<Self> {
-        const TRACK_COMPARE_THRESHOLD: f64 = 2.9;
-
+    /// Every song in the "Songs" shelf of a search result for `query`, as the
+    /// candidate used for scoring it and the record stored if it's chosen. A
+    /// search without results has no such shelf.
+    fn create_candidates_from_raw(data: &Value, query: &str) -> Result<Vec<(Candidate, Self)>> {
         let contents: &Vec<Value> = data
             .as_array()
-            .ok_or(Error::DatabaseError("no data array".to_string()))?;
+            .ok_or_else(|| Error::missing_field("YouTube", "search", "data array"))?;
 
         let mut songs: Option<RawMusicShelfRenderer> = None;
 
@@ -325,7 +426,7 @@ impl YouTube {
             if let Ok(music_shelf_renderer) = serde_json::from_value::<RawMusicShelfRenderer>(
                 content["musicShelfRenderer"].to_owned(),
             ) {
-                if let Some(run) = music_shelf_renderer.title.runs.get(0) {
+                if let Some(run) = music_shelf_renderer.title.runs.first() {
                     if run.text == "Songs" {
                         songs = Some(music_shelf_renderer);
                         break;
@@ -334,124 +435,632 @@ impl YouTube {
             }
         }
 
-        let id: &str;
-        let mut thumbnails: Vec<String> = Vec::new();
-
-        let songs: RawMusicShelfRenderer =
-            songs.ok_or(Error::DatabaseError("no songs".to_string()))?;
-        for song in songs.contents {
-            let first_flex_run: &Vec<RawRun> = &song
-                .music_responsive_list_item_renderer
-                .flex_columns
-                .get(0)
-                .ok_or(Error::DatabaseError("no first flex run".to_string()))?
-                .music_responsive_list_item_flex_column_renderer
-                .text
-                .runs;
-
-            let second_flex_run: &Vec<RawRun> = &song
-                .music_responsive_list_item_renderer
-                .flex_columns
-                .get(1)
-                .ok_or(Error::DatabaseError("no second flex run".to_string()))?
-                .music_responsive_list_item_flex_column_renderer
-                .text
-                .runs;
+        let songs: RawMusicShelfRenderer = songs.ok_or_else(|| Error::NotFound {
+            service: "YouTube".to_string(),
+            query: query.to_owned(),
+        })?;
+
+        let mut candidates: Vec<(Candidate, Self)> = Vec::new();
+        for song in &songs.contents {
+            match Self::create_candidate_from_raw(song) {
+                Ok(candidate) => candidates.push(candidate),
+                Err(e) => log::debug!("Skipping YouTube search result: {}", e),
+            }
+        }
+        Ok(candidates)
+    }
+
+    fn create_candidate_from_raw(song: &RawMusicShelfRendererContent) -> Result<(Candidate, Self)> {
+        let first_flex_run: &Vec<RawRun> = &song
+            .music_responsive_list_item_renderer
+            .flex_columns
+            .first()
+            .ok_or_else(|| Error::missing_field("YouTube", "search", "first flex run"))?
+            .music_responsive_list_item_flex_column_renderer
+            .text
+            .runs;
+
+        let second_flex_run: &Vec<RawRun> = &song
+            .music_responsive_list_item_renderer
+            .flex_columns
+            .get(1)
+            .ok_or_else(|| Error::missing_field("YouTube", "search", "second flex run"))?
+            .music_responsive_list_item_flex_column_renderer
+            .text
+            .runs;
+
+        // `Artist & Artist • Album • 2:18`, sometimes after a `Song • ` label
+        let sections: Vec<&[RawRun]> = split_sections(second_flex_run);
+        if first_flex_run.is_empty() || sections.len() < 3 {
+            return Err(Error::unexpected_response(
+                "YouTube",
+                "search",
+                "too few flex runs",
+            ));
+        }
+        let artist_runs: Vec<&RawRun> = artist_runs(sections[sections.len() - 3]);
+        let album_run: &RawRun = sections[sections.len() - 2]
+            .first()
+            .ok_or_else(|| Error::missing_field("YouTube", "search", "album flex run"))?;
+        let duration_run: &RawRun = sections[sections.len() - 1]
+            .first()
+            .ok_or_else(|| Error::missing_field("YouTube", "search", "duration flex run"))?;
+
+        let id: &str = &first_flex_run[0]
+            .navigation_endpoint
+            .as_ref()
+            .ok_or_else(|| {
+                Error::missing_field("YouTube", "search", "first flex navigation endpoint")
+            })?
+            .watch_endpoint
+            .as_ref()
+            .ok_or_else(|| Error::missing_field("YouTube", "search", "first flex watch endpoint"))?
+            .video_id;
+
+        let thumbnails: Vec<String> = song
+            .music_responsive_list_item_renderer
+            .thumbnail
+            .as_ref()
+            .ok_or_else(|| Error::missing_field("YouTube", "search", "thumbnail"))?
+            .music_thumbnail_renderer
+            .thumbnail
+            .thumbnails
+            .iter()
+            .map(|thumbnail| thumbnail.url.to_owned())
+            .collect();
+
+        let artists: Vec<Artist> = artists_from_runs(&artist_runs);
+        let album_id: Option<&str> = browse_id(album_run);
+
+        let duration_ms: usize = raw_duration_to_miliseconds(&duration_run.text)?;
+        let url: String = format!("https://www.youtube.com/watch?v={}", id);
+
+        let candidate = Candidate {
+            id: id.to_owned(),
+            url: url.to_owned(),
+            name: first_flex_run[0].text.to_owned(),
+            artists: artist_runs.iter().map(|run| run.text.to_owned()).collect(),
+            album: Some(album_run.text.to_owned()),
+            duration_ms: Some(duration_ms),
+            ..Default::default()
+        };
+
+        let service = Self {
+            id: id.to_owned(),
+            name: first_flex_run[0].text.to_owned(),
+            url,
+            artists,
+            album: album_id.map(|a_id| album_record(a_id, &album_run.text, None)),
+            duration_ms,
+            music_video: None,
+            thumbnails,
+            provenance: None,
+        };
+
+        Ok((candidate, service))
+    }
+
+    /// The song `id`, as the track it's the source of. YouTube Music doesn't say
+    /// where a song is on its album, so it's always track 1 of disk 1.
+    pub async fn create_track_from_id(
+        client: &Client,
+        config: &ServiceConfig,
+        id: &str,
+    ) -> Result<Track> {
+        let data: Value = Self::get_raw_track_from_id(client, config, id).await?;
+        let raw_track: &Value = find_values(&data, "playlistPanelVideoRenderer")
+            .into_iter()
+            .find(|raw_track| raw_track["videoId"] == id)
+            .ok_or_else(|| Error::NotFound {
+                service: "YouTube".to_string(),
+                query: id.to_owned(),
+            })?;
+
+        Self::create_track_from_raw(&serde_json::from_value(raw_track.to_owned())?)
+    }
+
+    fn create_track_from_raw(raw_track: &RawPlaylistPanelVideoRenderer) -> Result<Track> {
+        // `Artist & Artist • Album • 2013`, videos that aren't songs have no album or year
+        let sections: Vec<&[RawRun]> = split_sections(&raw_track.long_byline_text.runs);
+        if sections.len() < 3 {
+            return Err(Error::TrackError(format!(
+                "{} is not a song",
+                raw_track.video_id
+            )));
+        }
+        let artist_runs: Vec<&RawRun> = artist_runs(sections[sections.len() - 3]);
+        let album_run: &RawRun = sections[sections.len() - 2]
+            .first()
+            .ok_or_else(|| Error::missing_field("YouTube", "next", "album byline run"))?;
+        let release_year: usize = runs_text(sections[sections.len() - 1])
+            .trim()
+            .parse()
+            .map_err(|_| Error::missing_field("YouTube", "next", "release year"))?;
+
+        let name: String = runs_text(&raw_track.title.runs);
+        let duration_ms: usize = raw_duration_to_miliseconds(&runs_text(
+            &raw_track
+                .length_text
+                .as_ref()
+                .ok_or_else(|| Error::missing_field("YouTube", "next", "length"))?
+                .runs,
+        ))?;
+        let url: String = format!("https://www.youtube.com/watch?v={}", raw_track.video_id);
+
+        let service = Self {
+            id: raw_track.video_id.to_owned(),
+            name: name.to_owned(),
+            url,
+            artists: artists_from_runs(&artist_runs),
+            album: browse_id(album_run).map(|id| album_record(id, &album_run.text, None)),
+            duration_ms,
+            music_video: None,
+            thumbnails: raw_track
+                .thumbnail
+                .thumbnails
+                .iter()
+                .map(|thumbnail| thumbnail.url.to_owned())
+                .collect(),
+            provenance: Some(MatchProvenance::direct_id(&raw_track.video_id)),
+        };
+
+        Ok(Track {
+            name,
+            album: album_run.text.to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: artist_runs.iter().map(|run| run.text.to_owned()).collect(),
+            release_year,
+            release_month: None,
+            release_day: None,
+            is_explicit: is_explicit(raw_track.badges.as_ref()),
+            duration_ms,
+            isrc: None,
+            services: Services {
+                youtube: Some(service),
+                ..Default::default()
+            },
+            source_service: Source::YouTube,
+        })
+    }
+
+    /// Header of the album or playlist page `data`
+    fn get_browse_header(data: &Value, id: &str) -> Result<RawBrowseHeader> {
+        let raw_header: &Value = find_values(data, "musicResponsiveHeaderRenderer")
+            .into_iter()
+            .chain(find_values(data, "musicDetailHeaderRenderer"))
+            .next()
+            .ok_or_else(|| {
+                Error::missing_field("YouTube", &format!("browse {}", id), "page header")
+            })?;
+        parse_response("YouTube", &format!("browse {}", id), raw_header)
+    }
+
+    /// The album `album_id` (`MPREb_…`) with every song on it
+    pub async fn create_album_from_id(
+        client: &Client,
+        config: &ServiceConfig,
+        album_id: &str,
+    ) -> Result<album::Album> {
+        let data: Value = Self::get_raw_browse(client, config, Some(album_id), None).await?;
+        let header: RawBrowseHeader = Self::get_browse_header(&data, album_id)?;
+
+        // `Album • 2013`, or `Album • Artist • 2013` in the older header
+        let subtitle: Vec<&[RawRun]> = header
+            .subtitle
+            .as_ref()
+            .map(|subtitle| split_sections(&subtitle.runs))
+            .unwrap_or_default();
+        let album_artist_runs: Vec<&RawRun> = match &header.strapline_text_one {
+            Some(strapline) => artist_runs(&strapline.runs),
+            None if subtitle.len() >= 3 => artist_runs(subtitle[subtitle.len() - 2]),
+            None => Vec::new(),
+        };
+        let release_year: usize = subtitle
+            .last()
+            .and_then(|year| runs_text(year).trim().parse().ok())
+            .ok_or_else(|| Error::missing_field("YouTube", "browse", "release year"))?;
+        let thumbnails: Vec<String> = header
+            .thumbnail
+            .as_ref()
+            .map(thumbnail_urls)
+            .unwrap_or_default();
 
-            if first_flex_run.len() < 1 || second_flex_run.len() < 5 {
-                return Err(Error::DatabaseError("too few flex runs".to_string()));
+        let rows: Vec<RawMusicShelfRendererContent> =
+            match find_values(&data, "musicShelfRenderer").first() {
+                Some(shelf) => serde_json::from_value(shelf["contents"].to_owned())?,
+                None => Vec::new(),
+            };
+
+        let mut album: album::Album = album::Album {
+            name: runs_text(&header.title.runs),
+            tracks: Vec::with_capacity(rows.len()),
+            id: album_id.to_owned(),
+            source_service: Source::YouTube,
+            release_year,
+            release_month: None,
+            release_day: None,
+            artists: album_artist_runs
+                .iter()
+                .map(|run| run.text.to_owned())
+                .collect(),
+            upc: None,
+            label: None,
+        };
+
+        for (i, row) in rows.iter().enumerate() {
+            match Self::create_album_track_from_raw(
+                &row.music_responsive_list_item_renderer,
+                i,
+                rows.len(),
+                &album,
+                &album_artist_runs,
+                &thumbnails,
+            ) {
+                Ok(track) => album.tracks.push(track),
+                Err(e) => log::warn!("Skipping track ({}) of {}: {}", i + 1, album.name, e),
             }
+        }
+
+        Ok(album)
+    }
+
+    /// The song in row `position` of `album`'s track list. Rows only name their
+    /// artists when they differ from the album's.
+    fn create_album_track_from_raw(
+        row: &RawMusicResponsiveListItemRenderer,
+        position: usize,
+        total_tracks: usize,
+        album: &album::Album,
+        album_artist_runs: &[&RawRun],
+        thumbnails: &[String],
+    ) -> Result<Track> {
+        let title_runs: &Vec<RawRun> = &row
+            .flex_columns
+            .first()
+            .ok_or_else(|| Error::missing_field("YouTube", "browse", "first flex run"))?
+            .music_responsive_list_item_flex_column_renderer
+            .text
+            .runs;
+        let id: &str = match &row.playlist_item_data {
+            Some(playlist_item_data) => &playlist_item_data.video_id,
+            None => title_runs
+                .first()
+                .and_then(|run| run.navigation_endpoint.as_ref()?.watch_endpoint.as_ref())
+                .map(|watch_endpoint| watch_endpoint.video_id.as_str())
+                .ok_or(Error::TrackError("unavailable".to_string()))?,
+        };
 
-            if track.compare_similarity_fuzzy(
-                &first_flex_run[0].text,
-                &second_flex_run[0].text,
-                &second_flex_run[2].text,
-                raw_duration_to_miliseconds(&second_flex_run[4].text)?,
-            ) >= TRACK_COMPARE_THRESHOLD
+        let mut artist_runs: Vec<&RawRun> = row
+            .flex_columns
+            .get(1)
+            .map(|column| {
+                artist_runs(
+                    &column
+                        .music_responsive_list_item_flex_column_renderer
+                        .text
+                        .runs,
+                )
+            })
+            .unwrap_or_default();
+        if artist_runs.is_empty() {
+            artist_runs = album_artist_runs.to_vec();
+        }
+
+        let duration_ms: usize = raw_duration_to_miliseconds(&runs_text(
+            &row.fixed_columns
+                .as_ref()
+                .and_then(|columns| columns.first())
+                .ok_or_else(|| Error::missing_field("YouTube", "browse", "duration column"))?
+                .music_responsive_list_item_fixed_column_renderer
+                .text
+                .runs,
+        ))?;
+        let track_number: usize = row
+            .index
+            .as_ref()
+            .and_then(|index| runs_text(&index.runs).trim().parse().ok())
+            .unwrap_or(position + 1);
+
+        let name: String = runs_text(title_runs);
+        let service = Self {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            url: format!("https://www.youtube.com/watch?v={}", id),
+            artists: artists_from_runs(&artist_runs),
+            album: Some(album_record(&album.id, &album.name, Some(total_tracks))),
+            duration_ms,
+            music_video: None,
+            thumbnails: thumbnails.to_vec(),
+            provenance: Some(MatchProvenance::direct_id(id)),
+        };
+
+        Ok(Track {
+            name,
+            album: album.name.to_owned(),
+            disk_number: 1,
+            track_number,
+            artists: artist_runs.iter().map(|run| run.text.to_owned()).collect(),
+            release_year: album.release_year,
+            release_month: None,
+            release_day: None,
+            is_explicit: is_explicit(row.badges.as_ref()),
+            duration_ms,
+            isrc: None,
+            services: Services {
+                youtube: Some(service),
+                ..Default::default()
+            },
+            source_service: Source::YouTube,
+        })
+    }
+
+    /// The playlist `playlist_id` (the `list=` parameter of its URL). Its rows
+    /// don't say which album a song is on, so every song is looked up by id.
+    pub async fn create_playlist_from_id(
+        client: &Client,
+        config: &ServiceConfig,
+        playlist_id: &str,
+    ) -> Result<Playlist> {
+        let playlist_id: &str = playlist_id.trim_start_matches("VL");
+        let browse_id: String = format!("VL{}", playlist_id);
+        let data: Value = Self::get_raw_browse(client, config, Some(&browse_id), None).await?;
+        let header: RawBrowseHeader = Self::get_browse_header(&data, playlist_id)?;
+
+        let mut contents: Vec<Value> = find_values(&data, "musicPlaylistShelfRenderer")
+            .first()
+            .and_then(|shelf| shelf["contents"].as_array())
+            .cloned()
+            .ok_or_else(|| Error::NotFound {
+                service: "YouTube".to_string(),
+                query: playlist_id.to_owned(),
+            })?;
+
+        // long playlists end with a token for the next page of rows
+        let mut ids: Vec<String> = Vec::new();
+        loop {
+            ids.extend(contents.iter().filter_map(|content| {
+                content["musicResponsiveListItemRenderer"]["playlistItemData"]["videoId"]
+                    .as_str()
+                    .map(str::to_owned)
+            }));
+            let token: String = match contents.last().and_then(|content| {
+                content["continuationItemRenderer"]["continuationEndpoint"]["continuationCommand"]
+                    ["token"]
+                    .as_str()
+            }) {
+                Some(token) => token.to_owned(),
+                None => break,
+            };
+            let mut next_page: Value =
+                Self::get_raw_browse(client, config, None, Some(&token)).await?;
+            contents = match next_page["onResponseReceivedActions"][0]
+                ["appendContinuationItemsAction"]["continuationItems"]
+                .take()
             {
-                id = &first_flex_run[0]
-                    .navigation_endpoint
-                    .as_ref()
-                    .ok_or(Error::DatabaseError(
-                        "no first flex navigation endpoint".to_string(),
-                    ))?
-                    .watch_endpoint
-                    .as_ref()
-                    .ok_or(Error::DatabaseError(
-                        "no first flex watch endpoint".to_string(),
-                    ))?
-                    .video_id;
-
-                for thumbnail in song
-                    .music_responsive_list_item_renderer
-                    .thumbnail
-                    .music_thumbnail_renderer
-                    .thumbnail
-                    .thumbnails
-                {
-                    thumbnails.push(thumbnail.url.to_owned())
-                }
+                Value::Array(contents) => contents,
+                _ => Vec::new(),
+            };
+        }
 
-                let artist_id: Option<String> = match &second_flex_run[0].navigation_endpoint {
-                    Some(nav_opt) => match &nav_opt.browse_endpoint {
-                        Some(nav) => Some(nav.browse_id.to_owned()),
-                        None => None,
-                    },
-                    None => None,
-                };
-
-                let album_id: Option<String> = match &second_flex_run[2].navigation_endpoint {
-                    Some(nav_opt) => match &nav_opt.browse_endpoint {
-                        Some(nav) => Some(nav.browse_id.to_owned()),
-                        None => None,
-                    },
-                    None => None,
-                };
-
-                let duration_raw: &str = &second_flex_run[4].text;
-
-                return Ok(Self {
-                    id: id.to_owned(),
-                    name: first_flex_run[0].text.to_owned(),
-                    url: format!("https://www.youtube.com/watch?v={}", id),
-                    artists: match artist_id {
-                        Some(a_id) => vec![Artist {
-                            id: a_id.to_owned(),
-                            url: format!("https://music.youtube.com/browse/{}", a_id),
-                            name: second_flex_run[0].text.to_owned(),
-                        }],
-                        None => vec![],
-                    },
-                    album: match album_id {
-                        Some(a_id) => Some(Album {
-                            id: a_id.to_owned(),
-                            name: second_flex_run[2].text.to_owned(),
-                            url: format!("https://music.youtube.com/browse/{}", a_id),
-                            total_tracks: None,
-                            ean: None,
-                            upc: None,
-                        }),
-                        None => None,
-                    },
-                    duration_ms: raw_duration_to_miliseconds(duration_raw)?,
-                    music_video: None,
-                    thumbnails,
-                });
+        let track_futures = ids
+            .iter()
+            .map(|id| Self::create_track_from_id(client, config, id));
+        let mut tracks: Vec<Track> = Vec::with_capacity(ids.len());
+        for (i, result) in futures::future::join_all(track_futures)
+            .await
+            .into_iter()
+            .enumerate()
+        {
+            match result {
+                Ok(track) => tracks.push(track),
+                Err(e) => log::warn!("Skipping track ({}) {}: {}", i + 1, ids[i], e),
             }
         }
-        Err(Error::TrackError("unable to create Track".to_string()))
+
+        Ok(Playlist {
+            name: runs_text(&header.title.runs),
+            tracks,
+            id: playlist_id.to_owned(),
+            description: header.description.as_ref().and_then(|description| {
+                let runs: &Vec<Value> = find_values(description, "runs").first()?.as_array()?;
+                Some(runs.iter().filter_map(|run| run["text"].as_str()).collect())
+            }),
+            source_service: Source::YouTube,
+            curator: header
+                .strapline_text_one
+                .as_ref()
+                .map(|strapline| runs_text(&strapline.runs)),
+            image: header
+                .thumbnail
+                .as_ref()
+                .and_then(|thumbnail| thumbnail_urls(thumbnail).pop()),
+        })
+    }
+}
+
+impl YouTubeProvider {
+    pub fn new() -> Self {
+        Self::with_config(ServiceConfig::youtube())
+    }
+
+    pub fn with_config(config: ServiceConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Default for YouTubeProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MusicService for YouTubeProvider {
+    fn name(&self) -> &str {
+        "YouTube"
+    }
+
+    fn source(&self) -> Source {
+        Source::YouTube
+    }
+
+    fn storefront(&self) -> Option<&str> {
+        Some(&self.config.storefront)
+    }
+
+    fn service_record(&self, track: &Track) -> Option<Value> {
+        serde_json::to_value(track.services.youtube.as_ref()?).ok()
+    }
+
+    fn set_service_record(&self, track: &mut Track, record: Value) -> Result<()> {
+        track.services.youtube = Some(serde_json::from_value(record)?);
+        Ok(())
+    }
+
+    async fn create_track_from_id(&self, client: &Client, id: &str) -> Result<Track> {
+        YouTube::create_track_from_id(client, &self.config, id).await
+    }
+
+    async fn create_playlist_from_id(&self, client: &Client, id: &str) -> Result<Playlist> {
+        YouTube::create_playlist_from_id(client, &self.config, id).await
+    }
+
+    async fn create_album_from_id(&self, client: &Client, id: &str) -> Result<album::Album> {
+        YouTube::create_album_from_id(client, &self.config, id).await
+    }
+
+    async fn create_service_from_id(
+        &self,
+        client: &Client,
+        track: &mut Track,
+        id: &str,
+    ) -> Result<()> {
+        track.services.youtube = YouTube::create_track_from_id(client, &self.config, id)
+            .await?
+            .services
+            .youtube;
+        Ok(())
+    }
+
+    async fn search(&self, client: &Client, track: &Track) -> Result<Vec<MatchReport>> {
+        YouTube::search(client, &self.config, track).await
+    }
+
+    async fn create_service_for_track(&self, client: &Client, track: &mut Track) -> Result<()> {
+        YouTube::create_service_for_track(client, &self.config, track).await
+    }
+
+    async fn download(
+        &self,
+        _client: &Client,
+        track: &Track,
+        path: &Path,
+        filename: &str,
+    ) -> Result<PathBuf> {
+        match &track.services.youtube {
+            Some(youtube) => youtube.download(path, filename).await,
+            None => Err(Error::DownloadError("no YouTube service".to_string())),
+        }
+    }
+}
+
+/// Every value under `key` anywhere in `value`, depth first
+fn find_values<'a>(value: &'a Value, key: &str) -> Vec<&'a Value> {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .flat_map(|(k, v)| match k == key {
+                true => vec![v],
+                false => find_values(v, key),
+            })
+            .collect(),
+        Value::Array(values) => values.iter().flat_map(|v| find_values(v, key)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn runs_text(runs: &[RawRun]) -> String {
+    runs.iter().map(|run| run.text.as_str()).collect()
+}
+
+/// Split runs like `Artist • Album • 2013` into their sections
+fn split_sections(runs: &[RawRun]) -> Vec<&[RawRun]> {
+    runs.split(|run| run.text == " • ").collect()
+}
+
+/// Runs of an `Artist, Artist & Artist` section, without the separators
+fn artist_runs(section: &[RawRun]) -> Vec<&RawRun> {
+    section
+        .iter()
+        .filter(|run| run.text != ", " && run.text != " & ")
+        .collect()
+}
+
+/// Id of the page a run links to, e.g. an artist or album
+fn browse_id(run: &RawRun) -> Option<&str> {
+    Some(
+        &run.navigation_endpoint
+            .as_ref()?
+            .browse_endpoint
+            .as_ref()?
+            .browse_id,
+    )
+}
+
+/// Artists of the runs that link to an artist page
+fn artists_from_runs(runs: &[&RawRun]) -> Vec<Artist> {
+    runs.iter()
+        .filter_map(|run| {
+            let artist_id: &str = browse_id(run)?;
+            Some(Artist {
+                id: artist_id.to_owned(),
+                url: format!("https://music.youtube.com/browse/{}", artist_id),
+                name: run.text.to_owned(),
+            })
+        })
+        .collect()
+}
+
+fn album_record(id: &str, name: &str, total_tracks: Option<usize>) -> Album {
+    Album {
+        id: id.to_owned(),
+        name: name.to_owned(),
+        url: format!("https://music.youtube.com/browse/{}", id),
+        total_tracks,
+        ean: None,
+        upc: None,
     }
 }
 
+/// URLs of the first list of thumbnails in `value`, smallest first
+fn thumbnail_urls(value: &Value) -> Vec<String> {
+    find_values(value, "thumbnails")
+        .first()
+        .and_then(|thumbnails| thumbnails.as_array())
+        .map(|thumbnails| {
+            thumbnails
+                .iter()
+                .filter_map(|thumbnail| thumbnail["url"].as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn is_explicit(badges: Option<&Value>) -> bool {
+    badges.is_some_and(|badges| {
+        find_values(badges, "iconType")
+            .iter()
+            .any(|icon| *icon == "MUSIC_EXPLICIT_BADGE")
+    })
+}
+
 fn raw_duration_to_miliseconds(raw_duration: &str) -> Result<usize> {
     let raw_parts = raw_duration.split(':');
     let mut base: usize = 1;
     let mut seconds: usize = 0;
     for part in raw_parts.rev() {
-        let num: usize = match part.parse() {
-            Ok(n) => n,
-            Err(..) => 0,
-        };
+        let num: usize = part.parse().unwrap_or(0);
 
         seconds += num * base;
         base *= 60;
@@ -464,18 +1073,19 @@ fn raw_duration_to_miliseconds(raw_duration: &str) -> Result<usize> {
 mod tests {
 
     use crate::{
+        album::Album,
+        config::ServiceConfig,
+        error::Error,
+        playlist::Playlist,
+        replay::ReplayServer,
         service::{Services, Source},
         track::Track,
+        youtube::YouTubeProvider,
     };
 
     #[tokio::test]
     async fn get_match() {
-        let example_services: Services = Services {
-            spotify: None,
-            apple_music: None,
-            youtube: None,
-            bandcamp: None,
-        };
+        let example_services: Services = Services::default();
 
         let mut example_track: Track = Track {
             name: "Duchess for Nothing".to_owned(),
@@ -497,7 +1107,174 @@ mod tests {
                     .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15")
                     .build()
                     .unwrap();
+        let server: ReplayServer =
+            ReplayServer::start("youtube/get_match", ServiceConfig::youtube()).await;
+
+        example_track
+            .add_service(&client, &YouTubeProvider::with_config(server.config()))
+            .await
+            .unwrap();
+
+        // the first song is too different to match
+        let youtube = example_track.services.youtube.unwrap();
+        assert_eq!(youtube.id, "bS3kqv1uQ5E");
+        assert_eq!(youtube.duration_ms, 138000);
+        assert_eq!(youtube.album.unwrap().id, "MPREb_fixturegenius");
+    }
+
+    #[tokio::test]
+    async fn empty_search_is_not_found() {
+        let mut example_track: Track = Track {
+            name: "Duchess for Nothing".to_owned(),
+            album: "Genius Fatigue".to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_owned()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            services: Services::default(),
+            isrc: Some("USZUD1215001".to_owned()),
+            source_service: Source::Spotify,
+        };
+
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("youtube/search_empty", ServiceConfig::youtube()).await;
+
+        let result = example_track
+            .add_service(&client, &YouTubeProvider::with_config(server.config()))
+            .await;
+
+        assert!(matches!(result, Err(Error::NotFound { .. })));
+        assert!(example_track.services.youtube.is_none());
+    }
+
+    #[tokio::test]
+    async fn search_ranks_best_match_first() {
+        let example_track: Track = Track {
+            name: "Duchess for Nothing".to_owned(),
+            album: "Genius Fatigue".to_owned(),
+            disk_number: 1,
+            track_number: 1,
+            artists: vec!["Tunabunny".to_owned()],
+            release_year: 2013,
+            release_month: None,
+            release_day: None,
+            is_explicit: false,
+            duration_ms: 138026,
+            services: Services::default(),
+            isrc: Some("USZUD1215001".to_owned()),
+            source_service: Source::Spotify,
+        };
+
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("youtube/get_match", ServiceConfig::youtube()).await;
+
+        let candidates = example_track
+            .search_service(&client, &YouTubeProvider::with_config(server.config()))
+            .await
+            .unwrap();
+
+        assert!(candidates.len() > 1);
+        assert_eq!(candidates[0].candidate.id, "bS3kqv1uQ5E");
+        assert!(candidates[0].is_match());
+        assert!(!candidates[1].is_match());
+    }
+
+    #[tokio::test]
+    async fn creates_track_from_id() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("youtube/track", ServiceConfig::youtube()).await;
+
+        let track: Track = Track::from_service_id(
+            &client,
+            &YouTubeProvider::with_config(server.config()),
+            "bS3kqv1uQ5E",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(track.name, "Duchess for Nothing");
+        assert_eq!(track.artists, vec!["Tunabunny"]);
+        assert_eq!(track.album, "Genius Fatigue");
+        assert_eq!(track.release_year, 2013);
+        assert_eq!(track.duration_ms, 138000);
+        assert!(!track.is_explicit);
+        assert_eq!(track.source_service, Source::YouTube);
+
+        let youtube = track.services.youtube.unwrap();
+        assert_eq!(youtube.artists[0].id, "UCfixturetunabunny");
+        assert_eq!(youtube.album.unwrap().id, "MPREb_fixturegenius");
+        assert_eq!(youtube.thumbnails.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn creates_album_from_id() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("youtube/album", ServiceConfig::youtube()).await;
+
+        let album: Album = Album::from_service_id(
+            &client,
+            &YouTubeProvider::with_config(server.config()),
+            "MPREb_fixturegenius",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(album.name, "Genius Fatigue");
+        assert_eq!(album.artists, vec!["Tunabunny"]);
+        assert_eq!(album.release_year, 2013);
+        // the unavailable second song is skipped
+        assert_eq!(album.tracks.len(), 2);
+        assert_eq!(album.tracks[0].artists, vec!["Tunabunny"]);
+        assert_eq!(album.tracks[1].track_number, 3);
+        assert_eq!(album.tracks[1].artists, vec!["Tunabunny", "Guest"]);
+        assert!(album.tracks[1].is_explicit);
+        assert_eq!(
+            album.tracks[1]
+                .services
+                .youtube
+                .as_ref()
+                .unwrap()
+                .album
+                .as_ref()
+                .unwrap()
+                .total_tracks,
+            Some(3)
+        );
+    }
+
+    #[tokio::test]
+    async fn creates_playlist_from_id() {
+        let client: reqwest::Client = reqwest::Client::new();
+        let server: ReplayServer =
+            ReplayServer::start("youtube/playlist", ServiceConfig::youtube()).await;
+
+        let playlist: Playlist = Playlist::from_service_id(
+            &client,
+            &YouTubeProvider::with_config(server.config()),
+            "PLfixturemix",
+        )
+        .await
+        .unwrap();
 
-        example_track.add_youtube(&client).await.unwrap();
+        assert_eq!(playlist.name, "Fixture Mix");
+        assert_eq!(playlist.curator.as_deref(), Some("songvert"));
+        assert_eq!(playlist.description.as_deref(), Some("Songs for testing"));
+        // the video that isn't a song is skipped, the last song is on the next page
+        let ids: Vec<&str> = playlist
+            .tracks
+            .iter()
+            .map(|track| track.services.youtube.as_ref().unwrap().id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["bS3kqv1uQ5E", "fixtureRad1"]);
+        assert_eq!(playlist.tracks[1].artists, vec!["Other Band", "Guest"]);
+        assert!(playlist.tracks[1].is_explicit);
     }
 }